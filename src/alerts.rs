@@ -0,0 +1,216 @@
+//! Price alert evaluation.
+//!
+//! Alerts are checked on every price update. Each one fires once when its
+//! condition becomes true, then stays quiet until the price retreats by the
+//! configured hysteresis (so a price hovering at the level doesn't spam) and
+//! the cooldown has passed.
+
+use crate::config::{AlertCondition, AlertConfig};
+use crate::number::NumberFormat;
+use std::collections::VecDeque;
+use std::time::Instant;
+
+/// A fired alert, waiting to be shown as a desktop notification.
+#[derive(Debug, Clone)]
+pub struct AlertEvent {
+    pub symbol: String,
+    pub summary: String,
+    pub body: String,
+    /// Icon filename of the coin, as in the config.
+    pub icon: String,
+}
+
+/// Runtime state of one configured alert.
+pub struct Alert {
+    pub config: AlertConfig,
+    /// Whether the condition may fire; cleared on firing until the price retreats.
+    armed: bool,
+    last_fired: Option<Instant>,
+    /// Prices within the window, oldest first (`Move` alerts only).
+    samples: VecDeque<(Instant, f64)>,
+    /// Which side of the open the price was last clearly on (`CrossOpen` only).
+    above_open: Option<bool>,
+}
+
+impl Alert {
+    pub fn new(config: AlertConfig) -> Self {
+        Self {
+            config,
+            armed: true,
+            last_fired: None,
+            samples: VecDeque::new(),
+            above_open: None,
+        }
+    }
+
+    /// Feed a new price. Returns a description of what happened, like
+    /// `"above $70,000"`, when the alert fires.
+    pub fn check(
+        &mut self,
+        price: f64,
+        open: f64,
+        now: Instant,
+        format_price: impl Fn(f64) -> String,
+        numbers: &NumberFormat,
+    ) -> Option<String> {
+        // (condition holds, how far the price is back on the quiet side in
+        // percent of the price, description)
+        let (triggered, retreat, description) = match self.config.condition {
+            AlertCondition::Above(level) => (
+                price >= level,
+                (level - price) / level * 100.0,
+                format!("above {}", format_price(level)),
+            ),
+            AlertCondition::Below(level) => (
+                price <= level,
+                (price - level) / level * 100.0,
+                format!("below {}", format_price(level)),
+            ),
+            AlertCondition::Move { percent, window } => {
+                self.samples.push_back((now, price));
+                while self.samples.front().is_some_and(|(t, _)| now.duration_since(*t) > window) {
+                    self.samples.pop_front();
+                }
+
+                let reference = self.samples.front().map(|(_, p)| *p).unwrap_or(price);
+                let moved = if reference > 0.0 { (price - reference) / reference * 100.0 } else { 0.0 };
+                (
+                    moved.abs() >= percent,
+                    percent - moved.abs(),
+                    format!("moved {} in {}m", numbers.percent(moved), window.as_secs() / 60),
+                )
+            }
+            AlertCondition::CrossOpen => {
+                if open <= 0.0 {
+                    return None;
+                }
+
+                // Only count a side once the price is clear of the open by the hysteresis
+                let deviation = (price - open) / open * 100.0;
+                let side = (deviation.abs() >= self.config.hysteresis).then_some(deviation > 0.0);
+                let crossed = matches!((self.above_open, side), (Some(a), Some(b)) if a != b);
+                if side.is_some() {
+                    self.above_open = side;
+                }
+
+                let direction = if deviation > 0.0 { "above" } else { "below" };
+                (crossed, f64::INFINITY, format!("crossed {} 24h open {}", direction, format_price(open)))
+            }
+        };
+
+        if !self.armed {
+            if retreat > self.config.hysteresis {
+                self.armed = true;
+            }
+            return None;
+        }
+
+        let cooling_down = self.last_fired
+            .is_some_and(|t| now.duration_since(t) < self.config.cooldown);
+        if !triggered || cooling_down {
+            return None;
+        }
+
+        self.armed = false;
+        self.last_fired = Some(now);
+        Some(description)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn alert(condition: AlertCondition, cooldown: u64) -> Alert {
+        Alert::new(AlertConfig {
+            symbol: "BTC/USD".into(),
+            condition,
+            cooldown: Duration::from_secs(cooldown),
+            hysteresis: 1.0,
+        })
+    }
+
+    /// Feed `price` at `secs` seconds after `start`.
+    fn feed(alert: &mut Alert, start: Instant, secs: u64, price: f64, open: f64) -> Option<String> {
+        let now = start + Duration::from_secs(secs);
+        alert.check(price, open, now, |p| format!("${}", p), &NumberFormat::default())
+    }
+
+    #[test]
+    fn above_rearms_past_hysteresis() {
+        let start = Instant::now();
+        let mut above = alert(AlertCondition::Above(100.0), 0);
+
+        assert_eq!(feed(&mut above, start, 0, 99.0, 0.0), None);
+        assert_eq!(feed(&mut above, start, 1, 100.0, 0.0).as_deref(), Some("above $100"));
+        // Hovering at the level, then dipping inside the 1% band, stays quiet
+        assert_eq!(feed(&mut above, start, 2, 101.0, 0.0), None);
+        assert_eq!(feed(&mut above, start, 3, 99.5, 0.0), None);
+        assert_eq!(feed(&mut above, start, 4, 100.5, 0.0), None);
+        // Retreating past the band re-arms it
+        assert_eq!(feed(&mut above, start, 5, 98.9, 0.0), None);
+        assert!(feed(&mut above, start, 6, 100.0, 0.0).is_some());
+    }
+
+    #[test]
+    fn below_rearms_past_hysteresis() {
+        let start = Instant::now();
+        let mut below = alert(AlertCondition::Below(100.0), 0);
+
+        assert_eq!(feed(&mut below, start, 0, 99.0, 0.0).as_deref(), Some("below $100"));
+        assert_eq!(feed(&mut below, start, 1, 100.5, 0.0), None);
+        assert_eq!(feed(&mut below, start, 2, 99.0, 0.0), None);
+        assert_eq!(feed(&mut below, start, 3, 101.5, 0.0), None);
+        assert!(feed(&mut below, start, 4, 99.0, 0.0).is_some());
+    }
+
+    #[test]
+    fn cooldown_holds_back_a_rearmed_alert() {
+        let start = Instant::now();
+        let mut above = alert(AlertCondition::Above(100.0), 60);
+
+        assert!(feed(&mut above, start, 0, 100.0, 0.0).is_some());
+        assert_eq!(feed(&mut above, start, 10, 90.0, 0.0), None);
+        // Re-armed, but still cooling down
+        assert_eq!(feed(&mut above, start, 20, 100.0, 0.0), None);
+        assert!(feed(&mut above, start, 61, 100.0, 0.0).is_some());
+    }
+
+    #[test]
+    fn move_only_compares_within_the_window() {
+        let start = Instant::now();
+        let condition = AlertCondition::Move { percent: 5.0, window: Duration::from_secs(60) };
+        let mut moved = alert(condition, 0);
+
+        assert_eq!(feed(&mut moved, start, 0, 100.0, 0.0), None);
+        assert_eq!(feed(&mut moved, start, 30, 103.0, 0.0), None);
+        // 100 has left the window, so this is +2.9% from 103, not +6%
+        assert_eq!(feed(&mut moved, start, 90, 106.0, 0.0), None);
+        assert_eq!(feed(&mut moved, start, 100, 111.5, 0.0).as_deref(), Some("moved +5.2% in 1m"));
+        // Back to +4.2% is within the 1 point band, so it doesn't re-arm
+        assert_eq!(feed(&mut moved, start, 110, 110.5, 0.0), None);
+        assert_eq!(feed(&mut moved, start, 115, 112.0, 0.0), None);
+        // Once the window has flattened out it re-arms
+        assert_eq!(feed(&mut moved, start, 200, 110.0, 0.0), None);
+        assert_eq!(feed(&mut moved, start, 210, 104.0, 0.0).as_deref(), Some("moved -5.5% in 1m"));
+    }
+
+    #[test]
+    fn cross_open_fires_on_changing_sides() {
+        let start = Instant::now();
+        let mut cross = alert(AlertCondition::CrossOpen, 0);
+
+        // No open yet, then the first side seen doesn't count as a cross
+        assert_eq!(feed(&mut cross, start, 0, 102.0, 0.0), None);
+        assert_eq!(feed(&mut cross, start, 1, 102.0, 100.0), None);
+        // Within 1% of the open doesn't pick a side
+        assert_eq!(feed(&mut cross, start, 2, 99.5, 100.0), None);
+        assert_eq!(
+            feed(&mut cross, start, 3, 98.0, 100.0).as_deref(),
+            Some("crossed below 24h open $100"),
+        );
+        assert_eq!(feed(&mut cross, start, 4, 97.0, 100.0), None);
+        assert!(feed(&mut cross, start, 5, 101.5, 100.0).is_some());
+    }
+}
@@ -0,0 +1,130 @@
+//! Binance WebSocket price source.
+
+use crate::config::Network;
+use crate::network;
+use crate::ticker::MarketStats;
+use crate::websocket::{BoxError, PriceSource, Tick};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+const BINANCE_WS: &str = "wss://stream.binance.com:9443/ws";
+const BINANCE_EXCHANGE_INFO: &str = "https://api.binance.com/api/v3/exchangeInfo";
+
+#[derive(Serialize)]
+struct SubscribeMessage {
+    method: String,
+    params: Vec<String>,
+    id: u32,
+}
+
+/// 24hr rolling window ticker event (`<symbol>@ticker` stream).
+#[derive(Deserialize)]
+struct TickerEvent {
+    #[serde(rename = "e")]
+    event: Option<String>,
+    #[serde(rename = "s")]
+    symbol: Option<String>,
+    #[serde(rename = "c")]
+    last: Option<String>,
+    #[serde(rename = "o")]
+    open: Option<String>,
+    #[serde(rename = "b")]
+    bid: Option<String>,
+    #[serde(rename = "a")]
+    ask: Option<String>,
+    #[serde(rename = "v")]
+    volume: Option<String>,
+    #[serde(rename = "w")]
+    vwap: Option<String>,
+    #[serde(rename = "l")]
+    low: Option<String>,
+    #[serde(rename = "h")]
+    high: Option<String>,
+}
+
+/// Binance spot ticker feed. Config symbols like `BTC/USDT` map to stream
+/// names like `btcusdt@ticker`.
+pub struct Binance {
+    /// Uppercase Binance symbol (as sent in events) to config symbol.
+    symbols: HashMap<String, String>,
+    ws_url: String,
+    network: Network,
+}
+
+impl Binance {
+    pub fn new(symbols: Vec<String>, network: &Network) -> Self {
+        Self {
+            symbols: symbols.into_iter()
+                .map(|s| (s.replace('/', "").to_uppercase(), s))
+                .collect(),
+            ws_url: network.binance_ws.clone().unwrap_or_else(|| BINANCE_WS.to_string()),
+            network: network.clone(),
+        }
+    }
+
+    fn to_tick(&self, event: TickerEvent) -> Option<Tick> {
+        let num = |v: Option<String>| v.and_then(|v| v.parse().ok());
+
+        Some(Tick {
+            symbol: self.symbols.get(event.symbol.as_deref()?)?.clone(),
+            price: event.last?.parse().ok()?,
+            open_24h: num(event.open),
+            stats: MarketStats {
+                bid: num(event.bid),
+                ask: num(event.ask),
+                volume: num(event.volume),
+                vwap: num(event.vwap),
+                low: num(event.low),
+                high: num(event.high),
+            },
+        })
+    }
+}
+
+impl PriceSource for Binance {
+    fn name(&self) -> &'static str {
+        "Binance"
+    }
+
+    fn ws_url(&self) -> String {
+        self.ws_url.clone()
+    }
+
+    fn subscribe_messages(&self) -> Vec<String> {
+        let subscribe = SubscribeMessage {
+            method: "SUBSCRIBE".to_string(),
+            params: self.symbols.keys()
+                .map(|s| format!("{}@ticker", s.to_lowercase()))
+                .collect(),
+            id: 1,
+        };
+
+        serde_json::to_string(&subscribe).into_iter().collect()
+    }
+
+    fn parse_message(&self, text: &str) -> Vec<Tick> {
+        let event = match serde_json::from_str::<TickerEvent>(text) {
+            Ok(event) if event.event.as_deref() == Some("24hrTicker") => event,
+            _ => return Vec::new(),
+        };
+
+        self.to_tick(event).into_iter().collect()
+    }
+
+    fn unknown_symbols(&self) -> Result<Vec<String>, BoxError> {
+        let client = network::http_client(&self.network);
+        let mut unknown = Vec::new();
+
+        // exchangeInfo rejects the whole request with 400 if any symbol is invalid
+        for (market, symbol) in &self.symbols {
+            let url = format!("{}?symbol={}", BINANCE_EXCHANGE_INFO, market);
+            match client.get(&url).send()?.status() {
+                reqwest::StatusCode::BAD_REQUEST => unknown.push(symbol.clone()),
+                status if !status.is_success() => return Err(format!("HTTP {} for {}", status, market).into()),
+                _ => {}
+            }
+        }
+
+        Ok(unknown)
+    }
+}
@@ -0,0 +1,146 @@
+//! Bitstamp WebSocket price source.
+
+use crate::config::Network;
+use crate::network;
+use crate::ticker::MarketStats;
+use crate::websocket::{BoxError, PriceSource, Tick};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+const BITSTAMP_WS: &str = "wss://ws.bitstamp.net";
+const BITSTAMP_REST: &str = "https://www.bitstamp.net/api/v2/ticker";
+
+#[derive(Serialize)]
+struct SubscribeMessage {
+    event: String,
+    data: SubscribeData,
+}
+
+#[derive(Serialize)]
+struct SubscribeData {
+    channel: String,
+}
+
+#[derive(Deserialize)]
+struct WsMessage {
+    event: Option<String>,
+    channel: Option<String>,
+    data: Option<TradeData>,
+}
+
+#[derive(Deserialize)]
+struct TradeData {
+    price: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct RestTicker {
+    open: Option<String>,
+    open_24: Option<String>,
+}
+
+/// Bitstamp trade feed. Config symbols like `BTC/USD` map to lowercase
+/// market names like `btcusd`; prices come from the `live_trades_*` channels.
+pub struct Bitstamp {
+    /// Bitstamp market name to config symbol.
+    symbols: HashMap<String, String>,
+    ws_url: String,
+    rest_url: String,
+    network: Network,
+}
+
+impl Bitstamp {
+    pub fn new(symbols: Vec<String>, network: &Network) -> Self {
+        Self {
+            symbols: symbols.into_iter()
+                .map(|s| (s.replace('/', "").to_lowercase(), s))
+                .collect(),
+            ws_url: network.bitstamp_ws.clone().unwrap_or_else(|| BITSTAMP_WS.to_string()),
+            rest_url: network.bitstamp_rest.clone().unwrap_or_else(|| BITSTAMP_REST.to_string()),
+            network: network.clone(),
+        }
+    }
+}
+
+impl PriceSource for Bitstamp {
+    fn name(&self) -> &'static str {
+        "Bitstamp"
+    }
+
+    fn ws_url(&self) -> String {
+        self.ws_url.clone()
+    }
+
+    fn subscribe_messages(&self) -> Vec<String> {
+        self.symbols.keys()
+            .filter_map(|market| {
+                serde_json::to_string(&SubscribeMessage {
+                    event: "bts:subscribe".to_string(),
+                    data: SubscribeData {
+                        channel: format!("live_trades_{}", market),
+                    },
+                }).ok()
+            })
+            .collect()
+    }
+
+    fn parse_message(&self, text: &str) -> Vec<Tick> {
+        let ws_msg = match serde_json::from_str::<WsMessage>(text) {
+            Ok(msg) if msg.event.as_deref() == Some("trade") => msg,
+            _ => return Vec::new(),
+        };
+
+        let market = ws_msg.channel.as_deref()
+            .and_then(|c| c.strip_prefix("live_trades_"));
+
+        match (market.and_then(|m| self.symbols.get(m)), ws_msg.data.and_then(|d| d.price)) {
+            (Some(symbol), Some(price)) => vec![Tick {
+                symbol: symbol.clone(),
+                price,
+                open_24h: None,
+                stats: MarketStats::default(),
+            }],
+            _ => Vec::new(),
+        }
+    }
+
+    fn fetches_opens(&self) -> bool {
+        true
+    }
+
+    fn fetch_open_prices(&self) -> Result<HashMap<String, f64>, BoxError> {
+        let mut opens = HashMap::new();
+        let client = network::http_client(&self.network);
+
+        // The ticker endpoint only serves one market per request.
+        for (market, symbol) in &self.symbols {
+            let url = format!("{}/{}/", self.rest_url, market);
+            let ticker: RestTicker = client.get(&url).send()?.error_for_status()?.json()?;
+
+            // `open_24` is the rolling 24h open; `open` is since midnight UTC.
+            if let Some(open) = ticker.open_24.or(ticker.open)
+                .and_then(|o| o.parse::<f64>().ok())
+            {
+                opens.insert(symbol.clone(), open);
+            }
+        }
+
+        Ok(opens)
+    }
+
+    fn unknown_symbols(&self) -> Result<Vec<String>, BoxError> {
+        let client = network::http_client(&self.network);
+        let mut unknown = Vec::new();
+
+        for (market, symbol) in &self.symbols {
+            let url = format!("{}/{}/", self.rest_url, market);
+            match client.get(&url).send()?.status() {
+                reqwest::StatusCode::NOT_FOUND => unknown.push(symbol.clone()),
+                status if !status.is_success() => return Err(format!("HTTP {} for {}", status, market).into()),
+                _ => {}
+            }
+        }
+
+        Ok(unknown)
+    }
+}
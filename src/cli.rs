@@ -0,0 +1,175 @@
+//! Command-line argument parsing.
+//!
+//! Arguments are handled here rather than by GTK so that non-GUI modes can
+//! run without a display.
+
+use crate::headless::{HeadlessOptions, Layout, TextFormat};
+use crate::waybar::WaybarOptions;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// What the binary should do, as selected on the command line.
+pub enum Mode {
+    /// Run the layer-shell overlay (the default).
+    Overlay {
+        /// Print how long frames take to draw.
+        frame_stats: bool,
+    },
+    /// Serve a local mock Kraken exchange.
+    MockExchange(MockOptions),
+    /// Print Waybar custom-module JSON instead of drawing an overlay.
+    Waybar(WaybarOptions),
+    /// Print the ticker as text on stdout instead of drawing an overlay.
+    Headless(HeadlessOptions),
+    /// Send one sample alert notification over D-Bus and exit.
+    TestNotification,
+    /// Send a command to the running overlay's control socket.
+    Ctl(Vec<String>),
+    /// Validate a config file and exit.
+    CheckConfig {
+        /// File to check; the user config when unset.
+        path: Option<PathBuf>,
+        /// Also ask the exchanges whether each pair exists.
+        online: bool,
+    },
+}
+
+/// Options for `--mock-exchange`.
+pub struct MockOptions {
+    pub port: u16,
+    /// TOML file of scripted price steps; random walk when unset.
+    pub script: Option<PathBuf>,
+}
+
+const USAGE: &str = "\
+Usage: waybar-crypto-ticker [OPTIONS]
+       waybar-crypto-ticker ctl <COMMAND>
+       waybar-crypto-ticker check-config [FILE] [--online]
+
+Options:
+  --mock-exchange       Serve a local mock Kraken exchange instead of the overlay
+    --port <PORT>       Port for the mock exchange (default 8790)
+    --script <FILE>     Replay scripted prices instead of a random walk
+  --waybar              Print JSON for a Waybar custom module instead of the overlay
+    --rotate <SECS>     Seconds to show each coin (default 5, 0 = only on signal)
+  --stdout              Print the ticker as text instead of the overlay
+    --format <FORMAT>   plain, ansi or pango (default ansi on a terminal, else plain)
+    --lines             One coin per line instead of the whole ticker line
+    --width <COLUMNS>   Scroll a window this many characters wide across the line
+    --once              Print once every coin has a price, then exit
+  --frame-stats         Print how long the overlay takes to draw each frame
+  --test-notification   Send a sample alert notification over D-Bus and exit
+  -h, --help            Show this help
+
+check-config reports unknown keys, bad values and missing icons with their
+line and column; --online also asks each exchange whether the pairs exist.
+
+Control commands (sent to the running overlay):
+  pause | resume | toggle            Stop or restart scrolling
+  show | hide | toggle-visible       Hide or show the overlay
+  add <BASE/QUOTE> [SOURCE]          Show another coin (until the next reload)
+  remove <BASE/QUOTE>                Stop showing a coin
+  speed <PX_PER_SEC | +N | -N>       Set or adjust the scroll speed
+  reload                             Re-read the config file
+  prices                             Print current prices as JSON
+";
+
+/// Parse the process arguments into a [`Mode`].
+///
+/// Prints usage and exits on `--help` or invalid arguments.
+pub fn parse() -> Mode {
+    match parse_args(std::env::args().skip(1)) {
+        Ok(Some(mode)) => mode,
+        Ok(None) => {
+            print!("{}", USAGE);
+            std::process::exit(0);
+        }
+        Err(e) => {
+            eprintln!("Error: {}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<Mode>, String> {
+    let mut mock = false;
+    let mut port = 8790;
+    let mut script = None;
+    let mut waybar = false;
+    let mut rotate = Some(Duration::from_secs(5));
+    let mut stdout = false;
+    let mut format = None;
+    let mut layout = Layout::Line;
+    let mut once = false;
+    let mut frame_stats = false;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--mock-exchange" => mock = true,
+            "--waybar" => waybar = true,
+            "--rotate" => {
+                let value = args.next().ok_or("--rotate needs a value")?;
+                let secs: f64 = value.parse()
+                    .ok()
+                    .filter(|s: &f64| s.is_finite() && *s >= 0.0)
+                    .ok_or_else(|| format!("invalid rotate interval '{}'", value))?;
+                rotate = (secs > 0.0).then(|| Duration::from_secs_f64(secs));
+            }
+            "--stdout" => stdout = true,
+            "--format" => {
+                let value = args.next().ok_or("--format needs a value")?;
+                format = Some(TextFormat::from_name(&value)
+                    .ok_or_else(|| format!("unknown format '{}' (expected plain, ansi or pango)", value))?);
+            }
+            "--lines" => layout = Layout::Lines,
+            "--width" => {
+                let value = args.next().ok_or("--width needs a value")?;
+                let width = value.parse().ok()
+                    .filter(|w: &usize| *w > 0)
+                    .ok_or_else(|| format!("invalid width '{}'", value))?;
+                layout = Layout::Scroll { width };
+            }
+            "--once" => once = true,
+            "--frame-stats" => frame_stats = true,
+            "--test-notification" => return Ok(Some(Mode::TestNotification)),
+            "check-config" => {
+                let mut path = None;
+                let mut online = false;
+                for arg in args.by_ref() {
+                    match arg.as_str() {
+                        "--online" => online = true,
+                        _ if path.is_none() && !arg.starts_with('-') => path = Some(PathBuf::from(arg)),
+                        other => return Err(format!("unexpected argument '{}' for check-config", other)),
+                    }
+                }
+                return Ok(Some(Mode::CheckConfig { path, online }));
+            }
+            "ctl" => {
+                let command: Vec<String> = args.collect();
+                if command.is_empty() {
+                    return Err("ctl needs a command".to_string());
+                }
+                return Ok(Some(Mode::Ctl(command)));
+            }
+            "--port" => {
+                let value = args.next().ok_or("--port needs a value")?;
+                port = value.parse().map_err(|_| format!("invalid port '{}'", value))?;
+            }
+            "--script" => {
+                script = Some(PathBuf::from(args.next().ok_or("--script needs a file")?));
+            }
+            other => return Err(format!("unknown argument '{}'", other)),
+        }
+    }
+
+    if mock {
+        Ok(Some(Mode::MockExchange(MockOptions { port, script })))
+    } else if stdout {
+        Ok(Some(Mode::Headless(HeadlessOptions { format, layout, once })))
+    } else if waybar {
+        Ok(Some(Mode::Waybar(WaybarOptions { rotate })))
+    } else {
+        Ok(Some(Mode::Overlay { frame_stats }))
+    }
+}
@@ -0,0 +1,142 @@
+//! Coinbase Advanced Trade WebSocket price source.
+
+use crate::config::Network;
+use crate::network;
+use crate::ticker::MarketStats;
+use crate::websocket::{BoxError, PriceSource, Tick};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+const COINBASE_WS: &str = "wss://advanced-trade-ws.coinbase.com";
+const COINBASE_PRODUCTS: &str = "https://api.coinbase.com/api/v3/brokerage/market/products";
+
+#[derive(Serialize)]
+struct SubscribeMessage<'a> {
+    #[serde(rename = "type")]
+    kind: &'a str,
+    product_ids: &'a [String],
+    channel: &'a str,
+}
+
+#[derive(Deserialize)]
+struct WsMessage {
+    channel: Option<String>,
+    events: Option<Vec<TickerEvent>>,
+}
+
+#[derive(Deserialize)]
+struct TickerEvent {
+    tickers: Option<Vec<TickerData>>,
+}
+
+#[derive(Deserialize)]
+struct TickerData {
+    product_id: Option<String>,
+    price: Option<String>,
+    price_percent_chg_24_h: Option<String>,
+    best_bid: Option<String>,
+    best_ask: Option<String>,
+    volume_24_h: Option<String>,
+    low_24_h: Option<String>,
+    high_24_h: Option<String>,
+}
+
+/// Coinbase ticker feed. Config symbols like `BTC/USD` map to product ids
+/// like `BTC-USD`.
+pub struct Coinbase {
+    product_ids: Vec<String>,
+    /// Product id to config symbol.
+    symbols: HashMap<String, String>,
+    ws_url: String,
+    network: Network,
+}
+
+impl Coinbase {
+    pub fn new(symbols: Vec<String>, network: &Network) -> Self {
+        let symbols: HashMap<String, String> = symbols.into_iter()
+            .map(|s| (s.replace('/', "-").to_uppercase(), s))
+            .collect();
+
+        Self {
+            product_ids: symbols.keys().cloned().collect(),
+            symbols,
+            ws_url: network.coinbase_ws.clone().unwrap_or_else(|| COINBASE_WS.to_string()),
+            network: network.clone(),
+        }
+    }
+}
+
+impl PriceSource for Coinbase {
+    fn name(&self) -> &'static str {
+        "Coinbase"
+    }
+
+    fn ws_url(&self) -> String {
+        self.ws_url.clone()
+    }
+
+    fn subscribe_messages(&self) -> Vec<String> {
+        // Heartbeats keep the connection open while a quiet pair has no trades.
+        ["ticker", "heartbeats"].iter()
+            .filter_map(|channel| {
+                serde_json::to_string(&SubscribeMessage {
+                    kind: "subscribe",
+                    product_ids: &self.product_ids,
+                    channel,
+                }).ok()
+            })
+            .collect()
+    }
+
+    fn parse_message(&self, text: &str) -> Vec<Tick> {
+        let ws_msg = match serde_json::from_str::<WsMessage>(text) {
+            Ok(msg) if msg.channel.as_deref() == Some("ticker") => msg,
+            _ => return Vec::new(),
+        };
+
+        ws_msg.events.unwrap_or_default().into_iter()
+            .flat_map(|event| event.tickers.unwrap_or_default())
+            .filter_map(|ticker| {
+                let symbol = self.symbols.get(ticker.product_id.as_deref()?)?;
+                let price = ticker.price?.parse::<f64>().ok()?;
+
+                // The feed reports the 24h change rather than the open itself.
+                let open_24h = ticker.price_percent_chg_24_h
+                    .and_then(|pct| pct.parse::<f64>().ok())
+                    .map(|pct| price / (1.0 + pct / 100.0));
+
+                let num = |v: Option<String>| v.and_then(|v| v.parse().ok());
+
+                Some(Tick {
+                    symbol: symbol.clone(),
+                    price,
+                    open_24h,
+                    stats: MarketStats {
+                        bid: num(ticker.best_bid),
+                        ask: num(ticker.best_ask),
+                        volume: num(ticker.volume_24_h),
+                        vwap: None,
+                        low: num(ticker.low_24_h),
+                        high: num(ticker.high_24_h),
+                    },
+                })
+            })
+            .collect()
+    }
+
+    fn unknown_symbols(&self) -> Result<Vec<String>, BoxError> {
+        let client = network::http_client(&self.network);
+        let mut unknown = Vec::new();
+
+        for (product_id, symbol) in &self.symbols {
+            let url = format!("{}/{}", COINBASE_PRODUCTS, product_id);
+            match client.get(&url).send()?.status() {
+                reqwest::StatusCode::NOT_FOUND | reqwest::StatusCode::BAD_REQUEST => unknown.push(symbol.clone()),
+                status if !status.is_success() => return Err(format!("HTTP {} for {}", status, product_id).into()),
+                _ => {}
+            }
+        }
+
+        Ok(unknown)
+    }
+}
@@ -3,17 +3,51 @@
 //! Loads settings from `~/.config/waybar-crypto-ticker/config.toml` if present,
 //! otherwise uses sensible defaults.
 
+use crate::number::{NumberFormat, Precision, MAX_DECIMALS};
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::path::PathBuf;
+use std::time::Duration;
 
 /// Runtime configuration for the ticker.
 #[derive(Debug, Clone)]
 pub struct Config {
-    pub monitor: Option<String>,
+    pub monitor: MonitorSelection,
     pub position: Position,
+    /// Per-output overrides, by connector name (e.g. `DP-3`).
+    pub outputs: HashMap<String, OutputConfig>,
     pub appearance: Appearance,
     pub animation: Animation,
+    pub mouse: Mouse,
+    pub network: Network,
+    pub portfolio: Portfolio,
+    /// `[numbers]`, resolved against the locale.
+    pub numbers: NumberFormat,
     pub coins: Vec<CoinConfig>,
+    /// `[[rates]]`: pairs streamed only to convert into `display_currency`.
+    pub rates: Vec<RatePair>,
+    pub alerts: Vec<AlertConfig>,
+    /// `[sparkline]` defaults, applied to coins added at runtime.
+    pub sparkline: Option<SparklineConfig>,
+}
+
+/// Outputs that get a ticker window.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MonitorSelection {
+    /// One window, on the output the compositor picks.
+    Default,
+    /// One window on every connected output.
+    All,
+    /// One window on each listed output that is connected.
+    Outputs(Vec<String>),
+}
+
+/// Settings for the window on one output, in place of the global ones.
+#[derive(Debug, Clone)]
+pub struct OutputConfig {
+    pub position: Position,
+    /// Symbols to show there; `None` shows every coin.
+    pub coins: Option<Vec<String>>,
 }
 
 #[derive(Debug, Clone)]
@@ -23,8 +57,12 @@ pub struct Position {
     pub margin_right: i32,
     pub margin_bottom: i32,
     pub margin_left: i32,
+    /// Ignored by the full-width anchors.
     pub width: i32,
     pub height: i32,
+    /// Reserve space along the anchored edge, like a bar, instead of
+    /// drawing over other surfaces.
+    pub exclusive: bool,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -33,40 +71,300 @@ pub enum Anchor {
     TopRight,
     BottomLeft,
     BottomRight,
+    /// Across the whole width of the output, at its top edge.
+    Top,
+    Bottom,
+    /// Across the whole width, halfway down.
+    Center,
+}
+
+impl Anchor {
+    /// Whether the window spans the output's width rather than `width`.
+    pub fn full_width(self) -> bool {
+        matches!(self, Anchor::Top | Anchor::Bottom | Anchor::Center)
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Appearance {
-    pub font_family: String,
-    pub font_size: f64,
+    /// Pango font description, e.g. `"Inter Bold 10"`.
+    pub font: String,
+    /// Fonts for individual template placeholders, by name; Pango font
+    /// descriptions merged over `font`, such as `"Bold"` or `"8"`.
+    pub spans: HashMap<String, String>,
     pub color_up: (f64, f64, f64),
     pub color_down: (f64, f64, f64),
     pub color_neutral: (f64, f64, f64),
+    pub color_stale: (f64, f64, f64),
     pub icon_size: u32,
+    /// Dim a coin once its last update is this old; `None` disables.
+    pub stale_after: Option<Duration>,
+    /// Default display template for coins without their own `format`.
+    pub format: String,
+    /// Currency code to convert every price into, e.g. `EUR`; `None` shows
+    /// each coin in its quote currency.
+    pub display_currency: Option<String>,
+    /// What flashes when a tick moves a coin's price.
+    pub tick_flash: TickFlash,
+    /// How long a tick flash takes to fade out.
+    pub tick_flash_duration: Duration,
+}
+
+/// What a tick that moves a coin's price flashes, in `color_up` or
+/// `color_down`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TickFlash {
+    None,
+    Background,
+    Text,
 }
 
 #[derive(Debug, Clone)]
 pub struct Animation {
     pub scroll_speed: f64,
     pub fps: u32,
+    pub mode: AnimationMode,
+    /// How long `page` and `roll` show each coin.
+    pub page_duration: Duration,
+    /// How long the cross-fade or roll to the next coin takes.
+    pub transition: Duration,
 }
 
-#[derive(Debug, Clone)]
+/// How the ticker presents its coins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnimationMode {
+    /// A continuous horizontal scroll.
+    Scroll,
+    /// One coin at a time, cross-fading to the next.
+    Page,
+    /// Every coin side by side in slots of equal width.
+    Static,
+    /// One coin at a time, rolling up to the next like a departure board.
+    Roll,
+}
+
+/// Pointer interaction with the overlay.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mouse {
+    /// Stop scrolling while the pointer is over the ticker.
+    pub pause_on_hover: bool,
+    /// Show a coin's full figures when hovering it.
+    pub tooltip: bool,
+    pub on_click: ClickAction,
+    pub wheel: WheelAction,
+    /// Pixels (`nudge`) or pixels per second (`speed`) per wheel step.
+    pub wheel_step: f64,
+}
+
+/// What clicking a coin does. URLs and commands may use the placeholders
+/// `{symbol}`, `{base}`, `{quote}` and `{source}`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClickAction {
+    None,
+    /// Open the pair's trade page on its exchange.
+    Trade,
+    /// Open a URL in the default browser.
+    Open(String),
+    /// Run a shell command.
+    Command(String),
+}
+
+/// What the mouse wheel does over the ticker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WheelAction {
+    None,
+    /// Move the ticker back or forth.
+    Nudge,
+    /// Change the scroll speed.
+    Speed,
+}
+
+/// How holdings (coins with an `amount`) are shown.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Portfolio {
+    /// Lead the ticker with a segment summing every holding.
+    pub show_total: bool,
+    /// Display template for the total segment.
+    pub total_format: String,
+    /// Mask amounts and values, e.g. while screen sharing.
+    pub blur: bool,
+}
+
+/// Outbound connection settings shared by every exchange.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Network {
+    /// Endpoint overrides, e.g. for a relay or `--mock-exchange`.
+    pub kraken_ws: Option<String>,
+    pub kraken_rest: Option<String>,
+    pub coinbase_ws: Option<String>,
+    pub binance_ws: Option<String>,
+    pub bitstamp_ws: Option<String>,
+    pub bitstamp_rest: Option<String>,
+    /// `http://`, `socks5://` or `socks5h://` proxy URL, with optional credentials.
+    pub proxy: Option<String>,
+    pub connect_timeout: Duration,
+    pub request_timeout: Duration,
+    /// First reconnect delay; doubles on each failure up to `backoff_max`.
+    pub backoff_initial: Duration,
+    pub backoff_max: Duration,
+    /// Reconnect when nothing, not even a heartbeat, arrives for this long,
+    /// and flag the feed stale after half this long without ticks; `None`
+    /// disables the watchdog.
+    pub idle_timeout: Option<Duration>,
+    /// How often to re-fetch 24h opens over REST, for sources whose feed
+    /// doesn't carry them; `None` fetches only at startup.
+    pub open_refresh: Option<Duration>,
+    /// PEM file of extra CA roots to trust in addition to the system store.
+    pub ca_bundle: Option<PathBuf>,
+    pub user_agent: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct CoinConfig {
     pub symbol: String,
     pub name: String,
     pub icon: String,
+    pub source: Source,
+    /// Display template, e.g. `"{price} {change_pct} H:{high} L:{low}"`.
+    pub format: String,
+    /// Inline price chart after the text; `None` when disabled.
+    pub sparkline: Option<SparklineConfig>,
+    /// Quantity held, in the base currency.
+    pub amount: Option<f64>,
+    /// Total paid for `amount`, in the quote currency.
+    pub cost_basis: Option<f64>,
+    /// Digits kept in prices and other amounts in the quote currency.
+    pub precision: Precision,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SparklineConfig {
+    pub width: f64,
+    /// Line colour; `None` follows the coin's up/down colour.
+    pub color: Option<(f64, f64, f64)>,
+    /// How much history the chart spans.
+    pub window: Duration,
+}
+
+/// A pair that isn't shown, streamed for its exchange rate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RatePair {
+    pub symbol: String,
+    pub source: Source,
+}
+
+/// A price level or move to notify about.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlertConfig {
+    pub symbol: String,
+    pub condition: AlertCondition,
+    /// Minimum time between two notifications from this alert.
+    pub cooldown: Duration,
+    /// How far the price must retreat past the trigger before the alert
+    /// re-arms, in percent of the price (percentage points for `Move`).
+    pub hysteresis: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlertCondition {
+    Above(f64),
+    Below(f64),
+    /// Price moved by at least this many percent within the window.
+    Move { percent: f64, window: Duration },
+    /// Price crossed the 24h open in either direction.
+    CrossOpen,
+}
+
+/// Exchange a coin's price is streamed from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Source {
+    Kraken,
+    Coinbase,
+    Binance,
+    Bitstamp,
+}
+
+impl Source {
+    /// Parse a source as written in the config, e.g. `"coinbase"`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "kraken" => Some(Source::Kraken),
+            "coinbase" => Some(Source::Coinbase),
+            "binance" => Some(Source::Binance),
+            "bitstamp" => Some(Source::Bitstamp),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Source::Kraken => "kraken",
+            Source::Coinbase => "coinbase",
+            Source::Binance => "binance",
+            Source::Bitstamp => "bitstamp",
+        }
+    }
+
+    /// Web page for trading `symbol` (`BASE/QUOTE`) on this exchange.
+    pub fn trade_url(self, symbol: &str) -> String {
+        let (base, quote) = symbol.split_once('/').unwrap_or((symbol, ""));
+        match self {
+            Source::Kraken => format!(
+                "https://pro.kraken.com/app/trade/{}-{}",
+                base.to_lowercase(),
+                quote.to_lowercase(),
+            ),
+            Source::Coinbase => format!("https://www.coinbase.com/advanced-trade/spot/{}-{}", base, quote),
+            Source::Binance => format!("https://www.binance.com/en/trade/{}_{}", base, quote),
+            Source::Bitstamp => format!(
+                "https://www.bitstamp.net/markets/{}/{}/",
+                base.to_lowercase(),
+                quote.to_lowercase(),
+            ),
+        }
+    }
 }
 
 /// TOML file structure for deserialization.
 #[derive(Deserialize, Default)]
 #[serde(default)]
-struct ConfigFile {
-    monitor: Option<String>,
+pub(crate) struct ConfigFile {
+    monitor: Option<MonitorFile>,
     position: PositionFile,
+    outputs: HashMap<String, OutputFile>,
     appearance: AppearanceFile,
     animation: AnimationFile,
+    mouse: MouseFile,
+    network: NetworkFile,
+    portfolio: PortfolioFile,
+    numbers: NumbersFile,
+    sparkline: SparklineFile,
     coins: Option<Vec<CoinFile>>,
+    rates: Vec<RateFile>,
+    alerts: Vec<AlertFile>,
+}
+
+/// `monitor = "DP-3"`, `monitor = "all"` or `monitor = ["DP-3", "HDMI-A-1"]`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MonitorFile {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// An `[outputs.<connector>]` table; unset fields use `[position]`.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct OutputFile {
+    anchor: Option<String>,
+    margin_top: Option<i32>,
+    margin_right: Option<i32>,
+    margin_bottom: Option<i32>,
+    margin_left: Option<i32>,
+    width: Option<i32>,
+    height: Option<i32>,
+    exclusive: Option<bool>,
+    coins: Option<Vec<String>>,
 }
 
 #[derive(Deserialize)]
@@ -79,6 +377,7 @@ struct PositionFile {
     margin_left: i32,
     width: i32,
     height: i32,
+    exclusive: bool,
 }
 
 impl Default for PositionFile {
@@ -91,6 +390,7 @@ impl Default for PositionFile {
             margin_left: 0,
             width: 320,
             height: 26,
+            exclusive: false,
         }
     }
 }
@@ -98,23 +398,40 @@ impl Default for PositionFile {
 #[derive(Deserialize)]
 #[serde(default)]
 struct AppearanceFile {
+    font: String,
+    spans: HashMap<String, String>,
+    /// Superseded by `font`, which takes precedence when set.
     font_family: String,
     font_size: f64,
     color_up: String,
     color_down: String,
     color_neutral: String,
+    color_stale: String,
     icon_size: u32,
+    format: String,
+    stale_after: u64,
+    display_currency: String,
+    tick_flash: String,
+    tick_flash_ms: u64,
 }
 
 impl Default for AppearanceFile {
     fn default() -> Self {
         Self {
+            font: String::new(),
+            spans: HashMap::new(),
             font_family: "monospace".to_string(),
             font_size: 11.0,
             color_up: "#4ec970".to_string(),
             color_down: "#e05555".to_string(),
             color_neutral: "#888888".to_string(),
+            color_stale: "#555555".to_string(),
             icon_size: 16,
+            format: "{price} {change}".to_string(),
+            stale_after: 120,
+            display_currency: String::new(),
+            tick_flash: "background".to_string(),
+            tick_flash_ms: 800,
         }
     }
 }
@@ -124,6 +441,9 @@ impl Default for AppearanceFile {
 struct AnimationFile {
     scroll_speed: f64,
     fps: u32,
+    mode: String,
+    page_seconds: f64,
+    transition_ms: u64,
 }
 
 impl Default for AnimationFile {
@@ -131,41 +451,257 @@ impl Default for AnimationFile {
         Self {
             scroll_speed: 30.0,
             fps: 60,
+            mode: "scroll".to_string(),
+            page_seconds: 5.0,
+            transition_ms: 400,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+struct MouseFile {
+    pause_on_hover: bool,
+    tooltip: bool,
+    on_click: String,
+    wheel: String,
+    wheel_step: f64,
+}
+
+impl Default for MouseFile {
+    fn default() -> Self {
+        Self {
+            pause_on_hover: true,
+            tooltip: true,
+            on_click: "trade".to_string(),
+            wheel: "nudge".to_string(),
+            wheel_step: 40.0,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+struct NetworkFile {
+    kraken_ws: Option<String>,
+    kraken_rest: Option<String>,
+    coinbase_ws: Option<String>,
+    binance_ws: Option<String>,
+    bitstamp_ws: Option<String>,
+    bitstamp_rest: Option<String>,
+    proxy: Option<String>,
+    connect_timeout: u64,
+    request_timeout: u64,
+    backoff_initial: u64,
+    backoff_max: u64,
+    idle_timeout: u64,
+    open_refresh: u64,
+    ca_bundle: Option<PathBuf>,
+    user_agent: Option<String>,
+}
+
+impl Default for NetworkFile {
+    fn default() -> Self {
+        Self {
+            kraken_ws: None,
+            kraken_rest: None,
+            coinbase_ws: None,
+            binance_ws: None,
+            bitstamp_ws: None,
+            bitstamp_rest: None,
+            proxy: None,
+            connect_timeout: 10,
+            request_timeout: 15,
+            backoff_initial: 1,
+            backoff_max: 60,
+            idle_timeout: 60,
+            open_refresh: 900,
+            ca_bundle: None,
+            user_agent: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+struct PortfolioFile {
+    show_total: bool,
+    total_format: String,
+    blur: bool,
+}
+
+impl Default for PortfolioFile {
+    fn default() -> Self {
+        Self {
+            show_total: true,
+            total_format: "TOTAL {value} {change}".to_string(),
+            blur: false,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+struct NumbersFile {
+    locale: String,
+    thousands: bool,
+    compact: bool,
+    percent_decimals: usize,
+}
+
+impl Default for NumbersFile {
+    fn default() -> Self {
+        Self {
+            locale: String::new(),
+            thousands: true,
+            compact: false,
+            percent_decimals: 1,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+struct SparklineFile {
+    enabled: bool,
+    width: f64,
+    color: String,
+    window: u64,
+}
+
+impl Default for SparklineFile {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            width: 40.0,
+            color: String::new(),
+            window: 3600,
         }
     }
 }
 
+/// Per-coin sparkline overrides; unset fields use `[sparkline]`.
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+struct CoinSparklineFile {
+    enabled: Option<bool>,
+    width: Option<f64>,
+    color: Option<String>,
+    window: Option<u64>,
+}
+
 #[derive(Deserialize, Clone)]
 struct CoinFile {
     symbol: String,
     name: String,
     icon: String,
+    #[serde(default)]
+    source: Option<String>,
+    #[serde(default)]
+    format: Option<String>,
+    #[serde(default)]
+    sparkline: CoinSparklineFile,
+    #[serde(default)]
+    amount: Option<f64>,
+    #[serde(default)]
+    cost_basis: Option<f64>,
+    #[serde(default)]
+    precision: Option<usize>,
+    #[serde(default)]
+    significant: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct RateFile {
+    symbol: String,
+    #[serde(default)]
+    source: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AlertFile {
+    symbol: String,
+    condition: String,
+    #[serde(default)]
+    value: Option<f64>,
+    #[serde(default)]
+    window: Option<u64>,
+    #[serde(default)]
+    cooldown: Option<u64>,
+    #[serde(default)]
+    hysteresis: Option<f64>,
+}
+
+impl CoinFile {
+    fn new(symbol: &str, name: &str, icon: &str) -> Self {
+        Self {
+            symbol: symbol.into(),
+            name: name.into(),
+            icon: icon.into(),
+            source: None,
+            format: None,
+            sparkline: CoinSparklineFile::default(),
+            amount: None,
+            cost_basis: None,
+            precision: None,
+            significant: None,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::from_file(ConfigFile::default())
+    }
 }
 
 impl Config {
-    /// Load configuration from file or use defaults.
+    /// Load configuration from file, or the defaults if there is none.
+    /// Exits if the file exists but can't be read or parsed.
     pub fn load() -> Self {
+        Self::try_load().unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        })
+    }
+
+    /// Load configuration, failing if the file exists but can't be read or
+    /// parsed. A missing file gives the defaults. Problems that don't stop
+    /// the file loading (unknown keys, bad colours, ...) are logged.
+    pub fn try_load() -> Result<Self, String> {
         let config_path = Self::config_path();
+        if !config_path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = std::fs::read_to_string(&config_path)
+            .map_err(|e| format!("Failed to read config: {}", e))?;
+        let problems = crate::validate::check(&contents);
+        let located = |p: &crate::validate::Problem| format!("{}:{}", config_path.display(), p);
 
-        let file_config = if config_path.exists() {
-            match std::fs::read_to_string(&config_path) {
-                Ok(contents) => toml::from_str(&contents).unwrap_or_else(|e| {
-                    eprintln!("Warning: Failed to parse config: {}", e);
-                    ConfigFile::default()
-                }),
-                Err(e) => {
-                    eprintln!("Warning: Failed to read config: {}", e);
-                    ConfigFile::default()
-                }
+        let config = Self::parse(&contents).map_err(|e| {
+            if problems.is_empty() {
+                return e;
             }
-        } else {
-            ConfigFile::default()
-        };
+            let lines: Vec<String> = problems.iter().map(located).collect();
+            format!("Failed to parse config:\n{}", lines.join("\n"))
+        })?;
+
+        for problem in &problems {
+            eprintln!("Warning: {}", located(problem));
+        }
 
-        Self::from_file(file_config)
+        Ok(config)
     }
 
-    fn config_path() -> PathBuf {
+    /// Parse config file contents.
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        toml::from_str(contents)
+            .map(Self::from_file)
+            .map_err(|e| format!("Failed to parse config: {}", e))
+    }
+
+    pub fn config_path() -> PathBuf {
         dirs::config_dir()
             .unwrap_or_else(|| PathBuf::from("."))
             .join("waybar-crypto-ticker/config.toml")
@@ -173,56 +709,206 @@ impl Config {
 
     fn from_file(f: ConfigFile) -> Self {
         let coins = f.coins.unwrap_or_else(Self::default_coins);
+        let position = Position {
+            anchor: parse_anchor(&f.position.anchor).unwrap_or(Anchor::TopRight),
+            margin_top: f.position.margin_top,
+            margin_right: f.position.margin_right,
+            margin_bottom: f.position.margin_bottom,
+            margin_left: f.position.margin_left,
+            width: f.position.width,
+            height: f.position.height,
+            exclusive: f.position.exclusive,
+        };
 
         Self {
-            monitor: f.monitor,
-            position: Position {
-                anchor: match f.position.anchor.as_str() {
-                    "top-left" => Anchor::TopLeft,
-                    "top-right" => Anchor::TopRight,
-                    "bottom-left" => Anchor::BottomLeft,
-                    "bottom-right" => Anchor::BottomRight,
-                    _ => Anchor::TopRight,
-                },
-                margin_top: f.position.margin_top,
-                margin_right: f.position.margin_right,
-                margin_bottom: f.position.margin_bottom,
-                margin_left: f.position.margin_left,
-                width: f.position.width,
-                height: f.position.height,
+            monitor: match f.monitor {
+                None => MonitorSelection::Default,
+                Some(MonitorFile::One(name)) if name == "all" => MonitorSelection::All,
+                Some(MonitorFile::One(name)) => MonitorSelection::Outputs(vec![name]),
+                Some(MonitorFile::Many(names)) if names.is_empty() => MonitorSelection::Default,
+                Some(MonitorFile::Many(names)) => MonitorSelection::Outputs(names),
             },
+            outputs: f.outputs.into_iter()
+                .map(|(name, o)| (name, output_for(&position, o)))
+                .collect(),
+            position,
             appearance: Appearance {
-                font_family: f.appearance.font_family,
-                font_size: f.appearance.font_size,
+                font: match f.appearance.font.trim() {
+                    // Cairo's font size was in pixels
+                    "" => format!("{} {}px", f.appearance.font_family, f.appearance.font_size),
+                    font => font.to_string(),
+                },
+                spans: f.appearance.spans.clone(),
                 color_up: parse_hex_color(&f.appearance.color_up).unwrap_or((0.31, 0.79, 0.44)),
                 color_down: parse_hex_color(&f.appearance.color_down).unwrap_or((0.88, 0.33, 0.33)),
                 color_neutral: parse_hex_color(&f.appearance.color_neutral).unwrap_or((0.53, 0.53, 0.53)),
+                color_stale: parse_hex_color(&f.appearance.color_stale).unwrap_or((0.33, 0.33, 0.33)),
                 icon_size: f.appearance.icon_size,
+                stale_after: match f.appearance.stale_after {
+                    0 => None,
+                    secs => Some(Duration::from_secs(secs)),
+                },
+                format: f.appearance.format.clone(),
+                display_currency: Some(f.appearance.display_currency.trim().to_uppercase())
+                    .filter(|c| !c.is_empty()),
+                tick_flash: match f.appearance.tick_flash_ms {
+                    0 => TickFlash::None,
+                    _ => parse_tick_flash(&f.appearance.tick_flash).unwrap_or(TickFlash::Background),
+                },
+                tick_flash_duration: Duration::from_millis(f.appearance.tick_flash_ms),
             },
             animation: Animation {
                 scroll_speed: f.animation.scroll_speed,
-                fps: f.animation.fps.max(1).min(120),
+                fps: f.animation.fps.clamp(1, 120),
+                mode: parse_animation_mode(&f.animation.mode).unwrap_or(AnimationMode::Scroll),
+                page_duration: Duration::from_secs_f64(f.animation.page_seconds.clamp(0.5, 3600.0)),
+                transition: Duration::from_millis(f.animation.transition_ms),
+            },
+            mouse: Mouse {
+                pause_on_hover: f.mouse.pause_on_hover,
+                tooltip: f.mouse.tooltip,
+                on_click: parse_click_action(&f.mouse.on_click),
+                wheel: parse_wheel_action(&f.mouse.wheel).unwrap_or(WheelAction::Nudge),
+                wheel_step: f.mouse.wheel_step.max(0.0),
+            },
+            network: Network {
+                kraken_ws: f.network.kraken_ws,
+                kraken_rest: f.network.kraken_rest,
+                coinbase_ws: f.network.coinbase_ws,
+                binance_ws: f.network.binance_ws,
+                bitstamp_ws: f.network.bitstamp_ws,
+                bitstamp_rest: f.network.bitstamp_rest,
+                proxy: f.network.proxy,
+                connect_timeout: Duration::from_secs(f.network.connect_timeout.max(1)),
+                request_timeout: Duration::from_secs(f.network.request_timeout.max(1)),
+                backoff_initial: Duration::from_secs(f.network.backoff_initial.max(1)),
+                backoff_max: Duration::from_secs(f.network.backoff_max.max(f.network.backoff_initial).max(1)),
+                idle_timeout: match f.network.idle_timeout {
+                    0 => None,
+                    secs => Some(Duration::from_secs(secs)),
+                },
+                open_refresh: match f.network.open_refresh {
+                    0 => None,
+                    secs => Some(Duration::from_secs(secs)),
+                },
+                ca_bundle: f.network.ca_bundle,
+                user_agent: f.network.user_agent
+                    .unwrap_or_else(|| crate::network::DEFAULT_USER_AGENT.to_string()),
+            },
+            portfolio: Portfolio {
+                show_total: f.portfolio.show_total,
+                total_format: f.portfolio.total_format,
+                blur: f.portfolio.blur,
             },
+            numbers: numbers_for(&f.numbers),
+            alerts: f.alerts.into_iter()
+                .filter(|a| coins.iter().any(|c| c.symbol == a.symbol))
+                .filter_map(alert_for)
+                .collect(),
             coins: coins.into_iter().map(|c| CoinConfig {
+                source: c.source.as_deref()
+                    .and_then(Source::from_name)
+                    .unwrap_or(Source::Kraken),
                 symbol: c.symbol,
                 name: c.name,
                 icon: c.icon,
+                format: c.format.unwrap_or_else(|| f.appearance.format.clone()),
+                sparkline: sparkline_for(&f.sparkline, &c.sparkline),
+                amount: c.amount.filter(|a| *a > 0.0),
+                cost_basis: c.cost_basis.filter(|_| c.amount.is_some_and(|a| a > 0.0)),
+                precision: match (c.precision, c.significant) {
+                    (Some(decimals), _) => Precision::Decimals(decimals.min(MAX_DECIMALS)),
+                    (None, Some(digits)) => Precision::Significant(digits.clamp(1, MAX_DECIMALS)),
+                    (None, None) => Precision::Auto,
+                },
             }).collect(),
+            rates: f.rates.into_iter()
+                .map(|r| RatePair {
+                    source: r.source.as_deref()
+                        .and_then(Source::from_name)
+                        .unwrap_or(Source::Kraken),
+                    symbol: r.symbol,
+                })
+                .collect(),
+            sparkline: sparkline_for(&f.sparkline, &CoinSparklineFile::default()),
+        }
+    }
+
+    /// Every pair to stream and its exchange: the coins, then any rates
+    /// needed for `display_currency`.
+    pub fn feeds(&self) -> Vec<(Source, String)> {
+        let mut feeds: Vec<(Source, String)> = self.coins.iter()
+            .map(|c| (c.source, c.symbol.clone()))
+            .collect();
+
+        for (source, symbol) in crate::currency::rate_pairs(self) {
+            if !feeds.iter().any(|(_, s)| *s == symbol) {
+                feeds.push((source, symbol));
+            }
+        }
+        feeds
+    }
+
+    /// Position of the window on an output, with its overrides applied.
+    pub fn position_for(&self, connector: Option<&str>) -> &Position {
+        connector.and_then(|c| self.outputs.get(c))
+            .map(|o| &o.position)
+            .unwrap_or(&self.position)
+    }
+
+    /// Symbols shown on an output, when it doesn't show every coin.
+    pub fn coins_for(&self, connector: Option<&str>) -> Option<&[String]> {
+        connector.and_then(|c| self.outputs.get(c))
+            .and_then(|o| o.coins.as_deref())
+    }
+
+    /// Add a coin at runtime with default name, icon and display settings.
+    pub fn add_coin(&mut self, symbol: &str, source: Source) -> Result<(), String> {
+        let base = match symbol.split_once('/') {
+            Some((base, quote)) if !base.is_empty() && !quote.is_empty() => base,
+            _ => return Err(format!("'{}' is not a BASE/QUOTE pair", symbol)),
+        };
+        if self.coins.iter().any(|c| c.symbol == symbol) {
+            return Err(format!("{} is already shown", symbol));
+        }
+
+        self.coins.push(CoinConfig {
+            symbol: symbol.to_string(),
+            name: base.to_string(),
+            icon: format!("{}.svg", base.to_lowercase()),
+            source,
+            format: self.appearance.format.clone(),
+            sparkline: self.sparkline.clone(),
+            amount: None,
+            cost_basis: None,
+            precision: Precision::Auto,
+        });
+        Ok(())
+    }
+
+    /// Remove a coin at runtime.
+    pub fn remove_coin(&mut self, symbol: &str) -> Result<(), String> {
+        let before = self.coins.len();
+        self.coins.retain(|c| c.symbol != symbol);
+        if self.coins.len() == before {
+            return Err(format!("{} is not shown", symbol));
         }
+        Ok(())
     }
 
     fn default_coins() -> Vec<CoinFile> {
         vec![
-            CoinFile { symbol: "BTC/USD".into(), name: "BTC".into(), icon: "btc.svg".into() },
-            CoinFile { symbol: "ETH/USD".into(), name: "ETH".into(), icon: "eth.svg".into() },
-            CoinFile { symbol: "SOL/USD".into(), name: "SOL".into(), icon: "sol.svg".into() },
-            CoinFile { symbol: "ADA/USD".into(), name: "ADA".into(), icon: "ada.svg".into() },
-            CoinFile { symbol: "XRP/USD".into(), name: "XRP".into(), icon: "xrp.svg".into() },
+            CoinFile::new("BTC/USD", "BTC", "btc.svg"),
+            CoinFile::new("ETH/USD", "ETH", "eth.svg"),
+            CoinFile::new("SOL/USD", "SOL", "sol.svg"),
+            CoinFile::new("ADA/USD", "ADA", "ada.svg"),
+            CoinFile::new("XRP/USD", "XRP", "xrp.svg"),
         ]
     }
 
     /// Get the icons directory path.
     /// Checks user directory first, falls back to system directory.
+    #[allow(dead_code)]
     pub fn icons_dir() -> PathBuf {
         let user_dir = dirs::data_dir()
             .unwrap_or_else(|| PathBuf::from("."))
@@ -258,15 +944,135 @@ impl Config {
     }
 
     /// Get the example config path for first-time setup.
+    #[allow(dead_code)]
     pub fn example_config_path() -> PathBuf {
         PathBuf::from("/usr/share/waybar-crypto-ticker/config.example.toml")
     }
 }
 
+/// Parse an anchor as written in the config, e.g. `"top-right"`.
+fn parse_anchor(name: &str) -> Option<Anchor> {
+    match name {
+        "top-left" => Some(Anchor::TopLeft),
+        "top-right" => Some(Anchor::TopRight),
+        "bottom-left" => Some(Anchor::BottomLeft),
+        "bottom-right" => Some(Anchor::BottomRight),
+        "top" => Some(Anchor::Top),
+        "bottom" => Some(Anchor::Bottom),
+        "center" => Some(Anchor::Center),
+        _ => None,
+    }
+}
+
+/// `on_click`: `"trade"`, a URL, a shell command, or empty for nothing.
+fn parse_click_action(value: &str) -> ClickAction {
+    match value.trim() {
+        "" | "none" => ClickAction::None,
+        "trade" => ClickAction::Trade,
+        url if url.starts_with("http://") || url.starts_with("https://") => ClickAction::Open(url.to_string()),
+        command => ClickAction::Command(command.to_string()),
+    }
+}
+
+fn parse_tick_flash(name: &str) -> Option<TickFlash> {
+    match name {
+        "none" => Some(TickFlash::None),
+        "background" => Some(TickFlash::Background),
+        "text" => Some(TickFlash::Text),
+        _ => None,
+    }
+}
+
+fn parse_animation_mode(name: &str) -> Option<AnimationMode> {
+    match name {
+        "scroll" => Some(AnimationMode::Scroll),
+        "page" => Some(AnimationMode::Page),
+        "static" => Some(AnimationMode::Static),
+        "roll" => Some(AnimationMode::Roll),
+        _ => None,
+    }
+}
+
+fn parse_wheel_action(name: &str) -> Option<WheelAction> {
+    match name {
+        "none" => Some(WheelAction::None),
+        "nudge" => Some(WheelAction::Nudge),
+        "speed" => Some(WheelAction::Speed),
+        _ => None,
+    }
+}
+
+fn numbers_for(n: &NumbersFile) -> NumberFormat {
+    let locale = Some(n.locale.trim()).filter(|l| !l.is_empty());
+    let mut numbers = NumberFormat::for_locale(locale);
+    if !n.thousands {
+        numbers.group = None;
+    }
+    numbers.compact = n.compact;
+    numbers.percent_decimals = n.percent_decimals.min(MAX_DECIMALS);
+    numbers
+}
+
+/// Resolve an `[outputs.<connector>]` table against the global position.
+fn output_for(defaults: &Position, o: OutputFile) -> OutputConfig {
+    OutputConfig {
+        position: Position {
+            anchor: o.anchor.as_deref().and_then(parse_anchor).unwrap_or(defaults.anchor),
+            margin_top: o.margin_top.unwrap_or(defaults.margin_top),
+            margin_right: o.margin_right.unwrap_or(defaults.margin_right),
+            margin_bottom: o.margin_bottom.unwrap_or(defaults.margin_bottom),
+            margin_left: o.margin_left.unwrap_or(defaults.margin_left),
+            width: o.width.unwrap_or(defaults.width),
+            height: o.height.unwrap_or(defaults.height),
+            exclusive: o.exclusive.unwrap_or(defaults.exclusive),
+        },
+        coins: o.coins,
+    }
+}
+
+/// Resolve a coin's sparkline settings against the global defaults.
+fn sparkline_for(defaults: &SparklineFile, coin: &CoinSparklineFile) -> Option<SparklineConfig> {
+    if !coin.enabled.unwrap_or(defaults.enabled) {
+        return None;
+    }
+
+    let color = coin.color.as_deref().unwrap_or(&defaults.color);
+    Some(SparklineConfig {
+        width: coin.width.unwrap_or(defaults.width).max(4.0),
+        color: parse_hex_color(color),
+        window: Duration::from_secs(coin.window.unwrap_or(defaults.window).max(60)),
+    })
+}
+
+/// Resolve an `[[alerts]]` entry, dropping invalid ones (which
+/// `validate::check` reports).
+fn alert_for(a: AlertFile) -> Option<AlertConfig> {
+    let value = a.value.filter(|v| *v > 0.0);
+
+    let condition = match a.condition.as_str() {
+        "above" => AlertCondition::Above(value?),
+        "below" => AlertCondition::Below(value?),
+        "move" => AlertCondition::Move {
+            percent: value?,
+            window: Duration::from_secs(a.window.unwrap_or(3600).max(60)),
+        },
+        "cross_open" => AlertCondition::CrossOpen,
+        _ => return None,
+    };
+
+    Some(AlertConfig {
+        symbol: a.symbol,
+        condition,
+        cooldown: Duration::from_secs(a.cooldown.unwrap_or(900)),
+        hysteresis: a.hysteresis.unwrap_or(0.5).max(0.0),
+    })
+}
+
 /// Parse a hex color string like "#4ec970" into RGB floats (0.0-1.0).
-fn parse_hex_color(hex: &str) -> Option<(f64, f64, f64)> {
+pub fn parse_hex_color(hex: &str) -> Option<(f64, f64, f64)> {
     let hex = hex.trim_start_matches('#');
-    if hex.len() != 6 {
+    // Check the digits first: slicing non-ASCII text by bytes can panic
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
         return None;
     }
 
@@ -0,0 +1,213 @@
+//! Unix control socket for runtime commands.
+//!
+//! The overlay listens on `$XDG_RUNTIME_DIR/waybar-crypto-ticker.sock`;
+//! `waybar-crypto-ticker ctl <command>` connects, sends the command as one
+//! line and prints the reply. Replies are `ok`, `error: <reason>`, or JSON
+//! for `prices`.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// How long the socket thread waits for the UI to handle a command.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A command accepted on the control socket.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Pause,
+    Resume,
+    TogglePause,
+    Show,
+    Hide,
+    ToggleVisible,
+    /// Add a coin, optionally naming its exchange (default kraken).
+    Add { symbol: String, source: Option<String> },
+    Remove { symbol: String },
+    /// Set the scroll speed, or adjust it when `relative`.
+    Speed { value: f64, relative: bool },
+    Reload,
+    Prices,
+}
+
+impl Command {
+    /// Parse a command line such as `add DOGE/USD binance`.
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        let command = match words.as_slice() {
+            ["pause"] => Command::Pause,
+            ["resume"] => Command::Resume,
+            ["toggle"] => Command::TogglePause,
+            ["show"] => Command::Show,
+            ["hide"] => Command::Hide,
+            ["toggle-visible"] => Command::ToggleVisible,
+            ["add", symbol] => Command::Add { symbol: symbol.to_string(), source: None },
+            ["add", symbol, source] => Command::Add {
+                symbol: symbol.to_string(),
+                source: Some(source.to_string()),
+            },
+            ["remove", symbol] => Command::Remove { symbol: symbol.to_string() },
+            ["speed", value] => {
+                let relative = value.starts_with('+') || value.starts_with('-');
+                let value = value.parse::<f64>()
+                    .map_err(|_| format!("invalid speed '{}'", value))?;
+                Command::Speed { value, relative }
+            }
+            ["reload"] => Command::Reload,
+            ["prices"] => Command::Prices,
+            [] => return Err("empty command".to_string()),
+            _ => return Err(format!("unknown command '{}'", line.trim())),
+        };
+
+        Ok(command)
+    }
+}
+
+/// A command waiting for the UI thread, with the way back to the client.
+pub struct Request {
+    pub command: Command,
+    reply: mpsc::Sender<String>,
+}
+
+impl Request {
+    pub fn respond(self, reply: impl Into<String>) {
+        let _ = self.reply.send(reply.into());
+    }
+}
+
+/// Path of the control socket.
+pub fn socket_path() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("waybar-crypto-ticker.sock"),
+        None => std::env::temp_dir()
+            .join(format!("waybar-crypto-ticker-{}.sock", unsafe { libc::getuid() })),
+    }
+}
+
+/// Listen on the control socket.
+///
+/// Spawns a background thread that accepts connections and forwards each
+/// command through the provided channel; the receiver must `respond`.
+pub fn listen(sender: mpsc::Sender<Request>) {
+    let path = socket_path();
+
+    // Only one instance runs, so a leftover socket is from a crash
+    let _ = std::fs::remove_file(&path);
+
+    // Create the socket owner-only from the start: a chmod after `bind`
+    // would leave a window where other users could connect
+    let umask = unsafe { libc::umask(0o177) };
+    let bound = UnixListener::bind(&path);
+    unsafe { libc::umask(umask) };
+
+    let listener = match bound {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Warning: Control socket unavailable at {}: {}", path.display(), e);
+            return;
+        }
+    };
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            if let Err(e) = handle_client(stream, &sender) {
+                eprintln!("Control socket: {}", e);
+            }
+        }
+    });
+}
+
+fn handle_client(
+    mut stream: UnixStream,
+    sender: &mpsc::Sender<Request>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    stream.set_read_timeout(Some(REPLY_TIMEOUT))?;
+
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+
+    let reply = match Command::parse(&line) {
+        Ok(command) => {
+            let (tx, rx) = mpsc::channel();
+            sender.send(Request { command, reply: tx })?;
+            rx.recv_timeout(REPLY_TIMEOUT)
+                .unwrap_or_else(|_| "error: no reply from the ticker".to_string())
+        }
+        Err(e) => format!("error: {}", e),
+    };
+
+    stream.write_all(reply.as_bytes())?;
+    stream.write_all(b"\n")?;
+    Ok(())
+}
+
+/// Send a command to the running overlay and return its reply.
+pub fn send(args: &[String]) -> Result<String, Box<dyn std::error::Error>> {
+    let path = socket_path();
+    let mut stream = UnixStream::connect(&path)
+        .map_err(|e| format!("can't reach {} ({}); is the ticker running?", path.display(), e))?;
+    stream.set_read_timeout(Some(REPLY_TIMEOUT + Duration::from_secs(1)))?;
+
+    stream.write_all(args.join(" ").as_bytes())?;
+    stream.write_all(b"\n")?;
+
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+    Ok(reply.trim_end().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands() {
+        let add = |symbol: &str, source: Option<&str>| Command::Add {
+            symbol: symbol.to_string(),
+            source: source.map(str::to_string),
+        };
+        let cases = [
+            ("pause", Command::Pause),
+            ("resume", Command::Resume),
+            ("toggle", Command::TogglePause),
+            ("  show \n", Command::Show),
+            ("hide", Command::Hide),
+            ("toggle-visible", Command::ToggleVisible),
+            ("add DOGE/USD", add("DOGE/USD", None)),
+            ("add DOGE/USD  binance", add("DOGE/USD", Some("binance"))),
+            ("remove BTC/USD", Command::Remove { symbol: "BTC/USD".to_string() }),
+            ("speed 40", Command::Speed { value: 40.0, relative: false }),
+            ("speed +10", Command::Speed { value: 10.0, relative: true }),
+            ("speed -5.5", Command::Speed { value: -5.5, relative: true }),
+            ("reload", Command::Reload),
+            ("prices", Command::Prices),
+        ];
+
+        for (line, expected) in cases {
+            assert_eq!(Command::parse(line), Ok(expected), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_commands() {
+        let cases = [
+            ("", "empty command"),
+            ("   ", "empty command"),
+            ("jump", "unknown command 'jump'"),
+            ("pause now", "unknown command 'pause now'"),
+            ("add", "unknown command 'add'"),
+            ("add DOGE/USD binance extra", "unknown command 'add DOGE/USD binance extra'"),
+            ("remove", "unknown command 'remove'"),
+            ("speed fast", "invalid speed 'fast'"),
+            ("speed", "unknown command 'speed'"),
+            ("Reload", "unknown command 'Reload'"),
+        ];
+
+        for (line, expected) in cases {
+            assert_eq!(Command::parse(line), Err(expected.to_string()), "{:?}", line);
+        }
+    }
+}
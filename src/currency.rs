@@ -0,0 +1,176 @@
+//! Quote currencies: how amounts in them are written, and which pairs to
+//! stream so every coin can be shown in `display_currency`.
+
+use crate::config::{Config, Source};
+use crate::number::{NumberFormat, Precision};
+
+/// Symbols of common quote currencies. Stablecoins keep the dollar sign
+/// they've always been shown with. Other currencies are written as their
+/// code after the amount, e.g. `12.50 CHF`.
+const SYMBOLS: [(&str, &str); 13] = [
+    ("USD", "$"),
+    ("USDT", "$"),
+    ("USDC", "$"),
+    ("DAI", "$"),
+    ("EUR", "€"),
+    ("GBP", "£"),
+    ("JPY", "¥"),
+    ("KRW", "₩"),
+    ("CAD", "CA$"),
+    ("AUD", "A$"),
+    ("BTC", "₿"),
+    ("XBT", "₿"),
+    ("ETH", "Ξ"),
+];
+
+/// Currencies in the order they're conventionally paired, base first:
+/// `ETH/BTC`, `BTC/USDT`, `USDT/EUR`, `EUR/USD`, `USD/JPY`. Codes not listed
+/// are taken to be other cryptocurrencies, which come before all of these.
+const PAIR_ORDER: [&str; 13] = [
+    "ETH", "BTC", "USDT", "USDC", "DAI", "EUR", "GBP", "AUD", "NZD", "USD", "CAD", "CHF", "JPY",
+];
+
+/// Quote currency of a `BASE/QUOTE` symbol, e.g. `EUR` for `ETH/EUR`.
+pub fn quote_of(symbol: &str) -> &str {
+    symbol.split_once('/').map(|(_, quote)| quote).unwrap_or("")
+}
+
+/// An amount in `currency`, written with `numbers` and its symbol placed
+/// for the locale.
+pub fn format_money(amount: f64, currency: &str, numbers: &NumberFormat, precision: Precision) -> String {
+    let number = numbers.number(amount, precision);
+
+    let symbol = SYMBOLS.iter().find(|(code, _)| *code == currency).map(|(_, s)| *s);
+    match symbol {
+        Some(symbol) if numbers.symbol_first => format!("{}{}", symbol, number),
+        Some(symbol) => format!("{} {}", number, symbol),
+        None if currency.is_empty() => number,
+        None => format!("{} {}", number, currency),
+    }
+}
+
+/// Pairs to stream, besides the coins, so every coin can be converted to
+/// `display_currency`: the `[[rates]]` entries, then a pair on the coin's
+/// own exchange for each quote currency that nothing else converts.
+pub fn rate_pairs(config: &Config) -> Vec<(Source, String)> {
+    let target = match &config.appearance.display_currency {
+        Some(target) => target.as_str(),
+        None => return Vec::new(),
+    };
+    let mut pairs: Vec<(Source, String)> = config.rates.iter()
+        .map(|r| (r.source, r.symbol.clone()))
+        .collect();
+
+    for coin in &config.coins {
+        let quote = quote_of(&coin.symbol);
+        if quote.is_empty() || quote == target {
+            continue;
+        }
+
+        let converts = |symbol: &str| connects(symbol, quote, target);
+        if config.coins.iter().any(|c| converts(&c.symbol)) || pairs.iter().any(|(_, s)| converts(s)) {
+            continue;
+        }
+        pairs.push((coin.source, conventional_pair(quote, target)));
+    }
+
+    pairs
+}
+
+/// Whether `symbol` is the pair of `a` and `b`, either way round.
+pub fn connects(symbol: &str, a: &str, b: &str) -> bool {
+    symbol.split_once('/').is_some_and(|pair| pair == (a, b) || pair == (b, a))
+}
+
+/// The way round exchanges usually list a pair of two currencies.
+fn conventional_pair(a: &str, b: &str) -> String {
+    let rank = |code: &str| PAIR_ORDER.iter().position(|c| *c == code).map(|i| i as i32).unwrap_or(-1);
+
+    if rank(a) <= rank(b) {
+        format!("{}/{}", a, b)
+    } else {
+        format!("{}/{}", b, a)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quote_is_after_the_slash() {
+        assert_eq!(quote_of("SOL/BTC"), "BTC");
+        assert_eq!(quote_of("ETH/EUR"), "EUR");
+        assert_eq!(quote_of("TOTAL"), "");
+    }
+
+    #[test]
+    fn places_the_symbol_for_the_locale() {
+        let us = NumberFormat::for_locale(Some("en_US"));
+        let de = NumberFormat::for_locale(Some("de_DE"));
+        let precision = Precision::Decimals(2);
+
+        assert_eq!(format_money(1234.5, "USDT", &us, precision), "$1,234.50");
+        assert_eq!(format_money(1234.5, "EUR", &de, precision), "1.234,50 €");
+        assert_eq!(format_money(12.5, "CHF", &us, precision), "12.50 CHF");
+        assert_eq!(format_money(12.5, "", &us, precision), "12.50");
+    }
+
+    #[test]
+    fn pairs_currencies_the_usual_way_round() {
+        assert_eq!(conventional_pair("EUR", "USD"), "EUR/USD");
+        assert_eq!(conventional_pair("USD", "EUR"), "EUR/USD");
+        assert_eq!(conventional_pair("BTC", "ETH"), "ETH/BTC");
+        assert_eq!(conventional_pair("USD", "JPY"), "USD/JPY");
+        assert_eq!(conventional_pair("USDT", "SOL"), "SOL/USDT");
+    }
+
+    #[test]
+    fn streams_a_rate_for_each_unconverted_quote() {
+        let config = Config::parse(r#"
+            [appearance]
+            display_currency = "USD"
+
+            [[coins]]
+            symbol = "SOL/BTC"
+            name = "Solana"
+            icon = "sol.svg"
+
+            [[coins]]
+            symbol = "BTC/USD"
+            name = "Bitcoin"
+            icon = "btc.svg"
+
+            [[coins]]
+            symbol = "ETH/EUR"
+            name = "Ether"
+            icon = "eth.svg"
+            source = "bitstamp"
+
+            [[coins]]
+            symbol = "XRP/GBP"
+            name = "XRP"
+            icon = "xrp.svg"
+
+            [[rates]]
+            symbol = "GBP/USD"
+        "#).unwrap();
+
+        // BTC/USD is already streamed as a coin, GBP/USD as a rate
+        assert_eq!(rate_pairs(&config), vec![
+            (Source::Kraken, "GBP/USD".to_string()),
+            (Source::Bitstamp, "EUR/USD".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn no_rates_without_a_display_currency() {
+        let config = Config::parse(r#"
+            [[coins]]
+            symbol = "ETH/EUR"
+            name = "Ether"
+            icon = "eth.svg"
+        "#).unwrap();
+        assert!(rate_pairs(&config).is_empty());
+    }
+}
@@ -0,0 +1,57 @@
+//! Drawing cost per frame, reported on stderr with `--frame-stats`.
+
+use std::time::{Duration, Instant};
+
+/// How often a summary is printed.
+const REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Frame timings of one window since the last report.
+pub struct FrameStats {
+    /// Names the window in reports, e.g. its output.
+    label: String,
+    since: Instant,
+    frames: u32,
+    /// Frames that re-rendered the strip rather than only blitting it.
+    renders: u32,
+    total: Duration,
+    max: Duration,
+}
+
+impl FrameStats {
+    pub fn new(label: &str) -> Self {
+        Self {
+            label: label.to_string(),
+            since: Instant::now(),
+            frames: 0,
+            renders: 0,
+            total: Duration::ZERO,
+            max: Duration::ZERO,
+        }
+    }
+
+    /// Count a frame that took `elapsed` to draw, printing a summary once
+    /// per interval.
+    pub fn record(&mut self, elapsed: Duration, rendered: bool) {
+        self.frames += 1;
+        self.renders += rendered as u32;
+        self.total += elapsed;
+        self.max = self.max.max(elapsed);
+
+        let window = self.since.elapsed();
+        if window < REPORT_INTERVAL {
+            return;
+        }
+
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        eprintln!(
+            "{}: {} frames ({:.1}/s), draw avg {:.3} ms, max {:.3} ms, {} strip renders",
+            self.label,
+            self.frames,
+            self.frames as f64 / window.as_secs_f64(),
+            ms(self.total) / self.frames as f64,
+            ms(self.max),
+            self.renders,
+        );
+        *self = Self::new(&self.label);
+    }
+}
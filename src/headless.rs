@@ -0,0 +1,351 @@
+//! Plain-text output (`--stdout`) for terminals and other status bars.
+//!
+//! Runs the price feeds without GTK and prints the same segments the
+//! overlay draws: the whole line, one coin per line, or a scrolling window
+//! of fixed width, as plain text, ANSI colours or Pango markup. Suits
+//! polybar, i3blocks, eww, tmux status lines and SSH sessions.
+
+use crate::config::Config;
+use crate::ticker::{escape_markup, Direction, Segment, TickerState};
+use crate::{notify, websocket};
+use std::io::{IsTerminal, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How often the output is re-evaluated when not scrolling.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Approximate width of a character cell, for converting
+/// `animation.scroll_speed` (pixels per second) into characters per second.
+const CELL_WIDTH_PX: f64 = 10.0;
+
+/// Characters in a text sparkline.
+const SPARKLINE_CELLS: usize = 8;
+const SPARKLINE_BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// How long `--once` waits for every coin to get a price.
+const ONCE_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// How text is styled.
+#[derive(Clone, Copy, PartialEq)]
+pub enum TextFormat {
+    Plain,
+    /// 24-bit ANSI colour escapes.
+    Ansi,
+    /// `<span foreground="...">` markup for Pango-based bars.
+    Pango,
+}
+
+impl TextFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "plain" => Some(TextFormat::Plain),
+            "ansi" => Some(TextFormat::Ansi),
+            "pango" => Some(TextFormat::Pango),
+            _ => None,
+        }
+    }
+}
+
+/// What each update prints.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Layout {
+    /// Every coin on one line, as the overlay shows them.
+    Line,
+    /// One coin per line.
+    Lines,
+    /// A window of `width` characters scrolling across the line.
+    Scroll { width: usize },
+}
+
+/// Options for `--stdout`.
+pub struct HeadlessOptions {
+    /// Styling; ANSI on a terminal and plain text otherwise when unset.
+    pub format: Option<TextFormat>,
+    pub layout: Layout,
+    /// Print once every coin has a price (or after a timeout), then exit.
+    pub once: bool,
+}
+
+/// One character of output and the colour it's drawn in.
+type Cell = (char, Option<(f64, f64, f64)>);
+
+/// Print ticker output until stdout closes (or once, with `--once`).
+pub fn run(options: HeadlessOptions) -> std::io::Result<()> {
+    let config = Config::load();
+    let state = Arc::new(Mutex::new(TickerState::new(&config)));
+
+    // Keep the sender alive: dropping it would stop the feeds
+    let (_config_tx, config_rx) = tokio::sync::watch::channel(config.clone());
+    let state_ws = Arc::clone(&state);
+    std::thread::spawn(move || {
+        websocket::run(&state_ws, config_rx);
+    });
+
+    let mut stdout = std::io::stdout().lock();
+    let terminal = stdout.is_terminal();
+    let format = options.format
+        .unwrap_or(if terminal { TextFormat::Ansi } else { TextFormat::Plain });
+
+    // Characters per second in scroll layout
+    let step = Duration::from_secs_f64(CELL_WIDTH_PX / config.animation.scroll_speed.max(1.0));
+    let started = Instant::now();
+    let mut offset = 0;
+    let mut last_step = Instant::now();
+    let mut last_refresh = Instant::now();
+    let mut last_output = String::new();
+    let mut printed_lines = 0;
+    let mut notifier: Option<notify::Notifier> = None;
+
+    loop {
+        let (segments, complete, events) = match state.lock() {
+            Ok(mut state) => {
+                // Stale ages tick over once a second
+                if last_refresh.elapsed() >= Duration::from_secs(1) {
+                    state.refresh();
+                    last_refresh = Instant::now();
+                }
+                let coins = state.segments.iter().filter(|s| s.symbol.is_some() && !s.is_total()).count();
+                (state.segments.clone(), coins == config.coins.len(), state.take_alert_events())
+            }
+            // Skip this frame
+            Err(_) => {
+                std::thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+        };
+
+        if !events.is_empty() {
+            let notifier = notifier.get_or_insert_with(notify::Notifier::new);
+            for event in &events {
+                notifier.send_blocking(event);
+            }
+        }
+
+        if options.once && !complete && started.elapsed() < ONCE_TIMEOUT {
+            std::thread::sleep(POLL_INTERVAL);
+            continue;
+        }
+
+        let lines = match options.layout {
+            Layout::Line => vec![render(&line_cells(&config, &segments, false), format)],
+            Layout::Lines => segments.iter()
+                .filter(|s| s.symbol.is_some())
+                .map(|s| render(&segment_cells(&config, s), format))
+                .collect(),
+            Layout::Scroll { width } => {
+                let cells = line_cells(&config, &segments, true);
+                if last_step.elapsed() >= step {
+                    offset += 1;
+                    last_step = Instant::now();
+                }
+                vec![render(&scroll_window(cells, offset, width), format)]
+            }
+        };
+        let lines = if segments.is_empty() { vec!["Connecting...".to_string()] } else { lines };
+
+        let output = lines.join("\n");
+        if output != last_output || options.once {
+            if terminal && !options.once {
+                // Redraw in place rather than scrolling the terminal
+                match options.layout {
+                    Layout::Lines if printed_lines > 0 => write!(stdout, "\x1b[{}A\x1b[J", printed_lines)?,
+                    Layout::Lines => {}
+                    _ => write!(stdout, "\r\x1b[K")?,
+                }
+                if options.layout == Layout::Lines {
+                    writeln!(stdout, "{}", output)?;
+                } else {
+                    write!(stdout, "{}", output)?;
+                }
+            } else if options.layout == Layout::Lines && !options.once {
+                // Blank line ends each block for readers that split on it
+                writeln!(stdout, "{}\n", output)?;
+            } else {
+                writeln!(stdout, "{}", output)?;
+            }
+            stdout.flush()?;
+            printed_lines = lines.len();
+            last_output = output;
+        }
+
+        if options.once {
+            return Ok(());
+        }
+
+        let sleep = match options.layout {
+            Layout::Scroll { .. } => step.saturating_sub(last_step.elapsed()).min(POLL_INTERVAL),
+            _ => POLL_INTERVAL,
+        };
+        std::thread::sleep(sleep);
+    }
+}
+
+/// All segments as one line of cells, separators uncoloured.
+///
+/// The segments end with a separator (the overlay's gap before the line
+/// repeats); it's kept only when `wrap` is set.
+fn line_cells(config: &Config, segments: &[Segment], wrap: bool) -> Vec<Cell> {
+    let end = match segments.last() {
+        Some(last) if last.symbol.is_none() && !wrap => segments.len() - 1,
+        _ => segments.len(),
+    };
+    segments[..end].iter().flat_map(|s| segment_cells(config, s)).collect()
+}
+
+/// A segment's text and text sparkline, in its direction colour.
+fn segment_cells(config: &Config, segment: &Segment) -> Vec<Cell> {
+    let color = segment.symbol.as_ref().map(|_| match segment.direction {
+        Direction::Up => config.appearance.color_up,
+        Direction::Down => config.appearance.color_down,
+        Direction::Neutral => config.appearance.color_neutral,
+        Direction::Stale => config.appearance.color_stale,
+    });
+
+    let mut text = segment.text.clone();
+    if let Some(sparkline) = &segment.sparkline {
+        if sparkline.points.len() >= 2 {
+            text.push(' ');
+            text.push_str(&text_sparkline(&sparkline.points));
+        }
+    }
+
+    text.chars().map(|c| (c, color)).collect()
+}
+
+/// Block characters for scaled sparkline points, resampled to a fixed width.
+fn text_sparkline(points: &[f64]) -> String {
+    if points.is_empty() {
+        return String::new();
+    }
+
+    (0..SPARKLINE_CELLS)
+        .map(|i| {
+            let index = i * (points.len() - 1) / (SPARKLINE_CELLS - 1);
+            let level = (points[index].clamp(0.0, 1.0) * (SPARKLINE_BLOCKS.len() - 1) as f64).round();
+            SPARKLINE_BLOCKS[level as usize]
+        })
+        .collect()
+}
+
+/// `width` cells of the (wrapping) line starting at `offset`.
+fn scroll_window(cells: Vec<Cell>, offset: usize, width: usize) -> Vec<Cell> {
+    if cells.len() <= width {
+        return cells;
+    }
+
+    (0..width).map(|i| cells[(offset + i) % cells.len()]).collect()
+}
+
+/// Style runs of same-coloured cells for the output format.
+fn render(cells: &[Cell], format: TextFormat) -> String {
+    let mut out = String::new();
+    let mut start = 0;
+
+    while start < cells.len() {
+        let color = cells[start].1;
+        let end = cells[start..].iter()
+            .position(|(_, c)| *c != color)
+            .map(|n| start + n)
+            .unwrap_or(cells.len());
+        let text: String = cells[start..end].iter().map(|(c, _)| *c).collect();
+
+        match (format, color) {
+            (TextFormat::Ansi, Some((r, g, b))) => {
+                out.push_str(&format!("\x1b[38;2;{};{};{}m{}\x1b[0m", byte(r), byte(g), byte(b), text));
+            }
+            (TextFormat::Pango, Some((r, g, b))) => {
+                out.push_str(&format!(
+                    "<span foreground=\"#{:02x}{:02x}{:02x}\">{}</span>",
+                    byte(r), byte(g), byte(b), escape_markup(&text),
+                ));
+            }
+            (TextFormat::Pango, None) => out.push_str(&escape_markup(&text)),
+            _ => out.push_str(&text),
+        }
+        start = end;
+    }
+
+    out
+}
+
+fn byte(component: f64) -> u8 {
+    (component.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cells(text: &str) -> Vec<Cell> {
+        text.chars().map(|c| (c, None)).collect()
+    }
+
+    fn text(cells: &[Cell]) -> String {
+        cells.iter().map(|(c, _)| *c).collect()
+    }
+
+    #[test]
+    fn scroll_window_wraps_around() {
+        assert_eq!(text(&scroll_window(cells("abcde"), 0, 3)), "abc");
+        assert_eq!(text(&scroll_window(cells("abcde"), 4, 3)), "eab");
+        assert_eq!(text(&scroll_window(cells("abcde"), 7, 3)), "cde");
+    }
+
+    #[test]
+    fn scroll_window_keeps_short_lines_whole() {
+        assert_eq!(text(&scroll_window(cells("abc"), 2, 5)), "abc");
+    }
+
+    #[test]
+    fn sparkline_spans_low_to_high() {
+        let line = text_sparkline(&[0.0, 0.5, 1.0]);
+        assert_eq!(line.chars().count(), SPARKLINE_CELLS);
+        assert_eq!(line.chars().next(), Some('▁'));
+        assert_eq!(line.chars().last(), Some('█'));
+    }
+
+    #[test]
+    fn sparkline_of_flat_history_is_level() {
+        // Flat history is scaled to the middle of the range
+        let line = text_sparkline(&[0.5; 20]);
+        assert_eq!(line, "▅".repeat(SPARKLINE_CELLS));
+    }
+
+    #[test]
+    fn sparkline_of_empty_history_is_empty() {
+        assert_eq!(text_sparkline(&[]), "");
+    }
+
+    #[test]
+    fn render_styles_runs_of_colour() {
+        let red = Some((1.0, 0.0, 0.0));
+        let line: Vec<Cell> = vec![('a', red), ('&', red), (' ', None), ('b', None)];
+
+        assert_eq!(render(&line, TextFormat::Plain), "a& b");
+        assert_eq!(render(&line, TextFormat::Ansi), "\x1b[38;2;255;0;0ma&\x1b[0m b");
+        assert_eq!(
+            render(&line, TextFormat::Pango),
+            "<span foreground=\"#ff0000\">a&amp;</span> b",
+        );
+    }
+
+    #[test]
+    fn renders_coin_templates() {
+        let config = Config::parse(r#"
+            [numbers]
+            locale = "en_US"
+
+            [[coins]]
+            symbol = "BTC/USD"
+            name = "Bitcoin"
+            icon = "btc.svg"
+            format = "{name} {price} {change} {nope}"
+        "#).unwrap();
+        let mut state = TickerState::new(&config);
+        state.update_price("BTC/USD", 67000.0, Some(66000.0), &Default::default());
+
+        let line = line_cells(&config, &state.segments, false);
+        assert_eq!(render(&line, TextFormat::Plain), "Bitcoin $67,000 +1.5%▲ {nope}");
+    }
+}
@@ -20,27 +20,35 @@ pub enum TickerVisibility {
 /// Watch for fullscreen events on the specified monitor.
 ///
 /// Spawns a background thread that connects to Hyprland's event socket
-/// and sends visibility updates through the provided channel.
+/// and sends visibility updates through the provided channel. The thread
+/// exits once the receiver is dropped (e.g. the monitor was unplugged).
 pub fn watch_fullscreen(target_monitor: String, sender: mpsc::Sender<TickerVisibility>) {
     std::thread::spawn(move || {
         loop {
             let monitor_id = match get_monitor_id(&target_monitor) {
                 Some(id) => id,
                 None => {
+                    if sender.send(TickerVisibility::Visible).is_err() {
+                        return;
+                    }
                     std::thread::sleep(Duration::from_secs(2));
                     continue;
                 }
             };
 
             // Check initial state
-            let _ = sender.send(visibility_for_monitor(monitor_id));
+            if sender.send(visibility_for_monitor(monitor_id)).is_err() {
+                return;
+            }
 
             if let Err(e) = event_loop(monitor_id, &sender) {
                 eprintln!("Hyprland IPC: {:?}", e);
             }
 
             std::thread::sleep(Duration::from_secs(2));
-            let _ = sender.send(visibility_for_monitor(monitor_id));
+            if sender.send(visibility_for_monitor(monitor_id)).is_err() {
+                return;
+            }
         }
     });
 }
@@ -135,17 +143,24 @@ fn event_loop(
     for line in reader.lines() {
         let line = line?;
 
-        if line.starts_with("fullscreen>>") {
+        let update = if line.starts_with("fullscreen>>") {
             let is_fullscreen = line.ends_with("1");
             if is_fullscreen {
-                if get_active_monitor_id() == Some(target_id) {
-                    let _ = sender.send(TickerVisibility::Hidden);
-                }
+                (get_active_monitor_id() == Some(target_id)).then_some(TickerVisibility::Hidden)
             } else {
-                let _ = sender.send(TickerVisibility::Visible);
+                Some(TickerVisibility::Visible)
             }
         } else if line.starts_with("activewindow>>") || line.starts_with("focusedmon>>") {
-            let _ = sender.send(visibility_for_monitor(target_id));
+            Some(visibility_for_monitor(target_id))
+        } else {
+            None
+        };
+
+        // Nobody is listening any more; let the caller notice and stop
+        if let Some(visibility) = update {
+            if sender.send(visibility).is_err() {
+                return Ok(());
+            }
         }
     }
 
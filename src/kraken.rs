@@ -0,0 +1,211 @@
+//! Kraken v2 WebSocket price source.
+
+use crate::config::Network;
+use crate::network;
+use crate::ticker::MarketStats;
+use crate::websocket::{BoxError, PriceSource, Tick};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const KRAKEN_WS: &str = "wss://ws.kraken.com/v2";
+const KRAKEN_REST: &str = "https://api.kraken.com/0/public/Ticker";
+
+/// Kraken REST API uses different symbol names than WebSocket.
+pub fn ws_to_rest_symbol(ws_symbol: &str) -> String {
+    match ws_symbol {
+        "BTC/USD" => "XXBTZUSD".to_string(),
+        "ETH/USD" => "XETHZUSD".to_string(),
+        "XRP/USD" => "XXRPZUSD".to_string(),
+        other => other.replace("/", "").to_uppercase(),
+    }
+}
+
+#[derive(Serialize)]
+struct SubscribeMessage {
+    method: String,
+    params: SubscribeParams,
+}
+
+#[derive(Serialize)]
+struct SubscribeParams {
+    channel: String,
+    symbol: Vec<String>,
+    snapshot: bool,
+}
+
+#[derive(Deserialize)]
+struct WsMessage {
+    channel: Option<String>,
+    data: Option<Vec<TickerData>>,
+}
+
+#[derive(Deserialize)]
+struct TickerData {
+    symbol: Option<String>,
+    last: Option<f64>,
+    bid: Option<f64>,
+    ask: Option<f64>,
+    volume: Option<f64>,
+    vwap: Option<f64>,
+    low: Option<f64>,
+    high: Option<f64>,
+    /// Absolute change over the rolling 24h window.
+    change: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct RestResponse {
+    #[serde(default)]
+    error: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct OhlcResponse {
+    #[serde(default)]
+    error: Vec<String>,
+    result: Option<HashMap<String, serde_json::Value>>,
+}
+
+/// OHLC candle intervals Kraken supports, in minutes.
+const OHLC_INTERVALS: [u64; 7] = [1, 5, 15, 30, 60, 240, 1440];
+
+/// Kraken returns at most this many candles per request.
+const OHLC_MAX_CANDLES: u64 = 720;
+
+/// Kraken spot ticker feed. Config symbols are used as-is (e.g. `BTC/USD`).
+pub struct Kraken {
+    symbols: Vec<String>,
+    ws_url: String,
+    rest_url: String,
+    network: Network,
+}
+
+impl Kraken {
+    pub fn new(symbols: Vec<String>, network: &Network) -> Self {
+        Self {
+            symbols,
+            ws_url: network.kraken_ws.clone().unwrap_or_else(|| KRAKEN_WS.to_string()),
+            rest_url: network.kraken_rest.clone().unwrap_or_else(|| KRAKEN_REST.to_string()),
+            network: network.clone(),
+        }
+    }
+}
+
+impl PriceSource for Kraken {
+    fn name(&self) -> &'static str {
+        "Kraken"
+    }
+
+    fn ws_url(&self) -> String {
+        self.ws_url.clone()
+    }
+
+    fn subscribe_messages(&self) -> Vec<String> {
+        let subscribe = SubscribeMessage {
+            method: "subscribe".to_string(),
+            params: SubscribeParams {
+                channel: "ticker".to_string(),
+                symbol: self.symbols.clone(),
+                snapshot: true,
+            },
+        };
+
+        serde_json::to_string(&subscribe).into_iter().collect()
+    }
+
+    fn parse_message(&self, text: &str) -> Vec<Tick> {
+        let ws_msg = match serde_json::from_str::<WsMessage>(text) {
+            Ok(msg) if msg.channel.as_deref() == Some("ticker") => msg,
+            _ => return Vec::new(),
+        };
+
+        ws_msg.data.unwrap_or_default().into_iter()
+            .filter_map(|ticker| {
+                let price = ticker.last?;
+                Some(Tick {
+                    symbol: ticker.symbol?,
+                    price,
+                    // The open comes from here alone: the feed's change covers
+                    // the rolling 24h window, while REST's is since midnight UTC.
+                    open_24h: ticker.change.map(|change| price - change),
+                    stats: MarketStats {
+                        bid: ticker.bid,
+                        ask: ticker.ask,
+                        volume: ticker.volume,
+                        vwap: ticker.vwap,
+                        low: ticker.low,
+                        high: ticker.high,
+                    },
+                })
+            })
+            .collect()
+    }
+
+    fn fetch_history(&self, window: Duration) -> Result<HashMap<String, Vec<(SystemTime, f64)>>, BoxError> {
+        // Finest candle size that still covers the window in one request
+        let minutes = window.as_secs() / 60;
+        let interval = OHLC_INTERVALS.iter()
+            .copied()
+            .find(|i| minutes / i <= OHLC_MAX_CANDLES)
+            .unwrap_or(1440);
+        let since = SystemTime::now().duration_since(UNIX_EPOCH)?.saturating_sub(window).as_secs();
+
+        // OHLC lives next to Ticker, including on overridden endpoints
+        let ohlc_url = match self.rest_url.strip_suffix("Ticker") {
+            Some(base) => format!("{}OHLC", base),
+            None => return Err("kraken_rest doesn't end in /Ticker; can't locate OHLC".into()),
+        };
+
+        let client = network::http_client(&self.network);
+        let mut history = HashMap::new();
+
+        // OHLC only accepts one pair per request
+        for symbol in &self.symbols {
+            let url = format!("{}?pair={}&interval={}&since={}",
+                ohlc_url, ws_to_rest_symbol(symbol), interval, since);
+            let data: OhlcResponse = client.get(&url).send()?.error_for_status()?.json()?;
+
+            if !data.error.is_empty() {
+                return Err(data.error.join(", ").into());
+            }
+
+            // Candles are [time, open, high, low, close, vwap, volume, count];
+            // the pair key may be Kraken's canonical name, so skip `last`.
+            let candles = data.result.unwrap_or_default().into_iter()
+                .find(|(key, _)| key != "last")
+                .and_then(|(_, v)| v.as_array().cloned())
+                .unwrap_or_default();
+
+            let points = candles.iter()
+                .filter_map(|candle| {
+                    let time = candle.get(0)?.as_u64()?;
+                    let close = candle.get(4)?.as_str()?.parse().ok()?;
+                    Some((UNIX_EPOCH + Duration::from_secs(time), close))
+                })
+                .collect();
+            history.insert(symbol.clone(), points);
+        }
+
+        Ok(history)
+    }
+
+    fn unknown_symbols(&self) -> Result<Vec<String>, BoxError> {
+        let client = network::http_client(&self.network);
+        let mut unknown = Vec::new();
+
+        // One pair per request: an unknown pair fails the whole query
+        for symbol in &self.symbols {
+            let url = format!("{}?pair={}", self.rest_url, ws_to_rest_symbol(symbol));
+            let data: RestResponse = client.get(&url).send()?.error_for_status()?.json()?;
+
+            if data.error.iter().any(|e| e.contains("Unknown asset pair")) {
+                unknown.push(symbol.clone());
+            } else if !data.error.is_empty() {
+                return Err(data.error.join(", ").into());
+            }
+        }
+
+        Ok(unknown)
+    }
+}
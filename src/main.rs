@@ -1,29 +1,104 @@
 //! waybar-crypto-ticker - A scrolling cryptocurrency ticker overlay for Waybar.
 //!
-//! Displays real-time cryptocurrency prices from Kraken's WebSocket API as a
+//! Displays real-time cryptocurrency prices from exchange WebSocket APIs as a
 //! smooth scrolling overlay that integrates with Waybar on Hyprland/Wayland.
 
 use gtk4::prelude::*;
-use gtk4::{glib, Application, ApplicationWindow, DrawingArea};
+use gtk4::{gdk, gio, glib, pango, Application, ApplicationWindow, DrawingArea};
 use gtk4_layer_shell::{Edge, Layer, LayerShell};
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::rc::Rc;
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+mod alerts;
+mod binance;
+mod bitstamp;
+mod cli;
+mod coinbase;
 mod config;
+mod control;
+mod currency;
+mod frame_stats;
+mod headless;
 mod hyprland;
+mod kraken;
+mod mock;
+mod network;
+mod notify;
+mod number;
+mod strip;
 mod ticker;
+mod validate;
+mod waybar;
 mod websocket;
 
-use config::{Anchor, Config};
-use ticker::TickerState;
+use config::{Anchor, AnimationMode, ClickAction, Config, MonitorSelection, WheelAction};
+use frame_stats::FrameStats;
+use strip::{HitBoxes, Strip, StripKey};
+use ticker::{ConnectionState, TickerState};
 
 const APP_ID: &str = "io.github.waybar-crypto-ticker";
 const PID_FILE: &str = "/tmp/waybar-crypto-ticker.pid";
 
+/// Quiet period after a config file change before reloading.
+const RELOAD_DELAY: Duration = Duration::from_millis(250);
+
 fn main() -> glib::ExitCode {
+    let frame_stats = match cli::parse() {
+        cli::Mode::Overlay { frame_stats } => frame_stats,
+        cli::Mode::MockExchange(options) => {
+            return match mock::run(&Config::load(), options) {
+                Ok(()) => glib::ExitCode::SUCCESS,
+                Err(e) => {
+                    eprintln!("Mock exchange failed: {}", e);
+                    glib::ExitCode::FAILURE
+                }
+            };
+        }
+        cli::Mode::Headless(options) => {
+            return match headless::run(options) {
+                Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => glib::ExitCode::SUCCESS,
+                Err(e) => {
+                    eprintln!("Output failed: {}", e);
+                    glib::ExitCode::FAILURE
+                }
+                Ok(()) => glib::ExitCode::SUCCESS,
+            };
+        }
+        cli::Mode::Waybar(options) => {
+            return match waybar::run(options) {
+                // Waybar closed the pipe
+                Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => glib::ExitCode::SUCCESS,
+                Err(e) => {
+                    eprintln!("Waybar output failed: {}", e);
+                    glib::ExitCode::FAILURE
+                }
+                Ok(()) => glib::ExitCode::SUCCESS,
+            };
+        }
+        cli::Mode::TestNotification => {
+            return if notify::send_test() { glib::ExitCode::SUCCESS } else { glib::ExitCode::FAILURE };
+        }
+        cli::Mode::CheckConfig { path, online } => {
+            let ok = validate::run_check(path.as_deref(), online);
+            return if ok { glib::ExitCode::SUCCESS } else { glib::ExitCode::FAILURE };
+        }
+        cli::Mode::Ctl(args) => {
+            return match control::send(&args) {
+                Ok(reply) => {
+                    println!("{}", reply);
+                    if reply.starts_with("error") { glib::ExitCode::FAILURE } else { glib::ExitCode::SUCCESS }
+                }
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    glib::ExitCode::FAILURE
+                }
+            };
+        }
+    };
+
     // Ignore real-time signals that Waybar sends to refresh modules
     ignore_realtime_signals();
 
@@ -39,8 +114,9 @@ fn main() -> glib::ExitCode {
         .application_id(APP_ID)
         .build();
 
-    app.connect_activate(build_ui);
-    app.run()
+    app.connect_activate(move |app| build_ui(app, frame_stats));
+    // Arguments were already handled by `cli::parse`
+    app.run_with_args::<&str>(&[])
 }
 
 /// Guard that removes PID file when dropped.
@@ -49,6 +125,7 @@ struct PidFileGuard;
 impl Drop for PidFileGuard {
     fn drop(&mut self) {
         let _ = std::fs::remove_file(PID_FILE);
+        let _ = std::fs::remove_file(control::socket_path());
     }
 }
 
@@ -78,16 +155,29 @@ fn acquire_instance_lock() -> bool {
     true
 }
 
-/// Load cryptocurrency icons from the data directory.
+/// Coin icons by filename, rasterised for one scale factor.
+type Icons = HashMap<String, gtk4::cairo::ImageSurface>;
+
+/// Key of a (possibly fractional) scale in the icon cache: the scale in
+/// 120ths, as Wayland's fractional-scale protocol reports it.
+fn scale_key(scale: f64) -> i32 {
+    (scale * 120.0).round() as i32
+}
+
+/// Load cryptocurrency icons from the data directory at `scale` device
+/// pixels per logical pixel, so they stay sharp on scaled outputs.
 /// Checks user directory (~/.local/share) first, then system directory (/usr/share).
-fn load_icons(config: &Config) -> HashMap<String, gtk4::cairo::ImageSurface> {
+fn load_icons(config: &Config, scale: f64) -> Icons {
     let mut icons = HashMap::new();
-    let icon_size = config.appearance.icon_size;
+    let scale = scale.max(1.0);
+    let icon_size = (config.appearance.icon_size as f64 * scale).round() as u32;
 
     for coin in &config.coins {
         // Use find_icon to check user dir first, then system dir
         if let Some(path) = Config::find_icon(&coin.icon) {
             if let Some(surface) = render_icon_to_surface(&path, icon_size) {
+                // Drawn at `icon_size` logical pixels
+                surface.set_device_scale(scale, scale);
                 icons.insert(coin.icon.clone(), surface);
             }
         }
@@ -201,26 +291,16 @@ fn render_png(path: &std::path::Path, size: u32) -> Option<resvg::tiny_skia::Pix
     Some(pixmap)
 }
 
-fn build_ui(app: &Application) {
+fn build_ui(app: &Application, frame_stats: bool) {
     let config = Config::load();
 
     let state = Arc::new(Mutex::new(TickerState::new(&config)));
-    let scroll_offset = Rc::new(RefCell::new(0.0f64));
-    let cached_width = Rc::new(RefCell::new(0.0f64));
-    let icons = Rc::new(load_icons(&config));
-    let config = Rc::new(config);
-
-    let window = ApplicationWindow::builder()
-        .application(app)
-        .default_width(config.position.width)
-        .default_height(config.position.height)
-        .decorated(false)
-        .build();
+    let icons = Rc::new(RefCell::new(HashMap::new()));
 
     // Transparent background
-    if let Some(display) = gtk4::gdk::Display::default() {
+    if let Some(display) = gdk::Display::default() {
         let provider = gtk4::CssProvider::new();
-        provider.load_from_data("window { background-color: transparent; }");
+        provider.load_from_string("window { background-color: transparent; }");
         gtk4::style_context_add_provider_for_display(
             &display,
             &provider,
@@ -228,210 +308,776 @@ fn build_ui(app: &Application) {
         );
     }
 
-    // Layer shell setup
-    window.init_layer_shell();
-    window.set_layer(Layer::Overlay);
-
-    // Monitor targeting
-    if let Some(ref monitor_name) = config.monitor {
-        if let Some(display) = gtk4::gdk::Display::default() {
-            let monitors = display.monitors();
-            for i in 0..monitors.n_items() {
-                if let Some(obj) = monitors.item(i) {
-                    if let Ok(monitor) = obj.downcast::<gtk4::gdk::Monitor>() {
-                        if monitor.connector().as_deref() == Some(monitor_name.as_str()) {
-                            window.set_monitor(&monitor);
-                            break;
-                        }
-                    }
-                }
+    let (config_tx, config_rx) = tokio::sync::watch::channel(config.clone());
+    let ui = Rc::new(Ui {
+        app: app.clone(),
+        windows: RefCell::new(Vec::new()),
+        config: Rc::new(RefCell::new(config)),
+        state: Arc::clone(&state),
+        icons,
+        config_tx,
+        paused: Cell::new(false),
+        user_hidden: Cell::new(false),
+        pending_reload: Cell::new(None),
+        config_monitor: RefCell::new(None),
+        frame_stats,
+        _hold: app.hold(),
+    });
+
+    // One window per selected output, following hot-plugs
+    ui.sync_windows();
+    if let Some(display) = gdk::Display::default() {
+        let weak = Rc::downgrade(&ui);
+        display.monitors().connect_items_changed(move |_, _, _, _| {
+            if let Some(ui) = weak.upgrade() {
+                ui.sync_windows();
             }
-        }
+        });
     }
 
-    // Position anchoring
-    match config.position.anchor {
-        Anchor::TopLeft => {
-            window.set_anchor(Edge::Top, true);
-            window.set_anchor(Edge::Left, true);
-        }
-        Anchor::TopRight => {
-            window.set_anchor(Edge::Top, true);
-            window.set_anchor(Edge::Right, true);
-        }
-        Anchor::BottomLeft => {
-            window.set_anchor(Edge::Bottom, true);
-            window.set_anchor(Edge::Left, true);
+    // Apply config edits live
+    ui.watch_config();
+
+    // Let coins go stale even when no updates arrive, and deliver alerts
+    let state_refresh = Arc::clone(&state);
+    let mut notifier: Option<notify::Notifier> = None;
+    glib::timeout_add_seconds_local(1, move || {
+        let events = match state_refresh.lock() {
+            Ok(mut state) => {
+                state.refresh();
+                state.take_alert_events()
+            }
+            Err(_) => Vec::new(),
+        };
+        if !events.is_empty() {
+            let notifier = notifier.get_or_insert_with(notify::Notifier::new);
+            for event in &events {
+                notifier.send(event);
+            }
         }
-        Anchor::BottomRight => {
-            window.set_anchor(Edge::Bottom, true);
-            window.set_anchor(Edge::Right, true);
+        glib::ControlFlow::Continue
+    });
+
+    // WebSocket connection
+    let state_ws = Arc::clone(&state);
+    std::thread::spawn(move || {
+        websocket::run(&state_ws, config_rx);
+    });
+
+    // Runtime commands from `waybar-crypto-ticker ctl`
+    let (ctl_tx, ctl_rx) = std::sync::mpsc::channel();
+    control::listen(ctl_tx);
+
+    let ui_ctl = Rc::clone(&ui);
+    glib::timeout_add_local(Duration::from_millis(100), move || {
+        while let Ok(request) = ctl_rx.try_recv() {
+            let reply = ui_ctl.handle(request.command.clone());
+            request.respond(reply);
         }
+        glib::ControlFlow::Continue
+    });
+}
+
+/// Anchor, margins, size and exclusive zone of the overlay window.
+fn apply_position(window: &ApplicationWindow, drawing_area: &DrawingArea, position: &config::Position) {
+    let (top, bottom, left, right) = match position.anchor {
+        Anchor::TopLeft => (true, false, true, false),
+        Anchor::TopRight => (true, false, false, true),
+        Anchor::BottomLeft => (false, true, true, false),
+        Anchor::BottomRight => (false, true, false, true),
+        Anchor::Top => (true, false, true, true),
+        Anchor::Bottom => (false, true, true, true),
+        // Anchored to neither vertical edge, so centred between them
+        Anchor::Center => (false, false, true, true),
+    };
+    window.set_anchor(Edge::Top, top);
+    window.set_anchor(Edge::Bottom, bottom);
+    window.set_anchor(Edge::Left, left);
+    window.set_anchor(Edge::Right, right);
+
+    window.set_margin(Edge::Top, position.margin_top);
+    window.set_margin(Edge::Right, position.margin_right);
+    window.set_margin(Edge::Bottom, position.margin_bottom);
+    window.set_margin(Edge::Left, position.margin_left);
+
+    // Full-width anchors get their width from the compositor; the draw
+    // function follows whatever is allocated
+    let width = (!position.anchor.full_width()).then_some(position.width);
+    window.set_default_size(width.unwrap_or(-1), position.height);
+    drawing_area.set_content_width(width.unwrap_or(0));
+    drawing_area.set_content_height(position.height);
+
+    if position.exclusive {
+        // Size the reserved strip from the window, so other surfaces move aside
+        window.auto_exclusive_zone_enable();
+    } else {
+        // Draw over Waybar rather than being pushed aside by it
+        window.set_exclusive_zone(-1);
     }
+}
+
+/// Handles to the running overlay, shared by the timers and the control
+/// socket.
+struct Ui {
+    app: Application,
+    /// One per output the ticker is shown on.
+    windows: RefCell<Vec<Rc<TickerWindow>>>,
+    config: Rc<RefCell<Config>>,
+    state: Arc<Mutex<TickerState>>,
+    /// Icons for each scale in use, by `scale_key`, loaded on first draw.
+    icons: Rc<RefCell<HashMap<i32, Icons>>>,
+    /// Restarts the price sources when coins or network settings change.
+    config_tx: tokio::sync::watch::Sender<Config>,
+    paused: Cell<bool>,
+    /// Hidden with `ctl hide`.
+    user_hidden: Cell<bool>,
+    /// Debounced reload after a config file change.
+    pending_reload: Cell<Option<glib::SourceId>>,
+    /// Kept alive so file change notifications keep arriving.
+    config_monitor: RefCell<Option<gio::FileMonitor>>,
+    /// Report drawing cost per window (`--frame-stats`).
+    frame_stats: bool,
+    /// Keeps the application running while no output has a window.
+    _hold: gio::ApplicationHoldGuard,
+}
 
-    window.set_margin(Edge::Top, config.position.margin_top);
-    window.set_margin(Edge::Right, config.position.margin_right);
-    window.set_margin(Edge::Bottom, config.position.margin_bottom);
-    window.set_margin(Edge::Left, config.position.margin_left);
-    window.set_namespace("waybar-crypto-ticker");
-    window.set_exclusive_zone(-1);
-    window.set_keyboard_mode(gtk4_layer_shell::KeyboardMode::None);
-
-    // Drawing area
-    let drawing_area = DrawingArea::new();
-    drawing_area.set_content_width(config.position.width);
-    drawing_area.set_content_height(config.position.height);
-
-    let state_draw = Arc::clone(&state);
-    let scroll_draw = Rc::clone(&scroll_offset);
-    let icons_draw = Rc::clone(&icons);
-    let cached_draw = Rc::clone(&cached_width);
-    let config_draw = Rc::clone(&config);
-
-    let last_segments: Rc<RefCell<Vec<ticker::Segment>>> = Rc::new(RefCell::new(Vec::new()));
-    let segments_draw = Rc::clone(&last_segments);
-
-    drawing_area.set_draw_func(move |_, cr, width, height| {
-        let segments = match state_draw.try_lock() {
-            Ok(state) => {
-                let segs = state.segments.clone();
-                *segments_draw.borrow_mut() = segs.clone();
-                segs
-            }
-            Err(_) => segments_draw.borrow().clone(),
+impl Ui {
+    /// Open a window on each selected output that lacks one, and close
+    /// those whose output was unplugged or deselected.
+    fn sync_windows(self: &Rc<Self>) {
+        let monitors: Vec<gdk::Monitor> = match gdk::Display::default() {
+            Some(display) => {
+                let list = display.monitors();
+                (0..list.n_items())
+                    .filter_map(|i| list.item(i))
+                    .filter_map(|obj| obj.downcast::<gdk::Monitor>().ok())
+                    .collect()
+            }
+            None => Vec::new(),
         };
 
-        let offset = *scroll_draw.borrow();
+        // A hot-plugged output may be listed before its name is known
+        for monitor in monitors.iter().filter(|m| m.connector().is_none()) {
+            let ui = Rc::downgrade(self);
+            monitor.connect_connector_notify(move |_| {
+                if let Some(ui) = ui.upgrade() {
+                    ui.sync_windows();
+                }
+            });
+        }
 
-        // Clear background
-        cr.set_operator(gtk4::cairo::Operator::Clear);
-        let _ = cr.paint();
-        cr.set_operator(gtk4::cairo::Operator::Over);
+        let named = monitors.into_iter().filter(|m| m.connector().is_some());
+        let wanted: Vec<Option<gdk::Monitor>> = match &self.config.borrow().monitor {
+            MonitorSelection::Default => vec![None],
+            MonitorSelection::All => named.map(Some).collect(),
+            MonitorSelection::Outputs(names) => {
+                let listed: Vec<_> = named
+                    .filter(|m| m.connector().is_some_and(|c| names.iter().any(|n| *n == c.as_str())))
+                    .map(Some)
+                    .collect();
+                // None of them connected: fall back to the default output
+                if listed.is_empty() { vec![None] } else { listed }
+            }
+        };
 
-        // Font setup
-        cr.select_font_face(
-            &config_draw.appearance.font_family,
-            gtk4::cairo::FontSlant::Normal,
-            gtk4::cairo::FontWeight::Normal,
-        );
-        cr.set_font_size(config_draw.appearance.font_size);
-
-        if segments.is_empty() {
-            let c = config_draw.appearance.color_neutral;
-            cr.set_source_rgb(c.0, c.1, c.2);
-            cr.move_to(10.0, (height as f64 + config_draw.appearance.font_size) / 2.0);
-            let _ = cr.show_text("Connecting...");
-            return;
+        let mut windows = self.windows.borrow_mut();
+        windows.retain(|ticker| {
+            let keep = wanted.contains(&ticker.monitor);
+            if !keep {
+                ticker.window.destroy();
+            }
+            keep
+        });
+
+        for monitor in wanted {
+            if windows.iter().all(|ticker| ticker.monitor != monitor) {
+                let ticker = Rc::new(TickerWindow::new(self, monitor));
+                ticker.update_visibility(self.user_hidden.get());
+                self.watch_fullscreen(&ticker);
+                self.watch_pointer(&ticker);
+                self.animate(&ticker);
+                windows.push(ticker);
+            }
         }
+    }
+
+    /// Hide a window while a fullscreen window is on its output.
+    fn watch_fullscreen(self: &Rc<Self>, ticker: &Rc<TickerWindow>) {
+        let connector = match ticker.connector() {
+            Some(connector) => connector,
+            None => return,
+        };
+        let (tx, rx) = std::sync::mpsc::channel();
+        hyprland::watch_fullscreen(connector, tx);
 
-        // Calculate widths
-        let icon_space = config_draw.appearance.icon_size as f64 + 4.0;
-        let mut total_width = 0.0;
-        let mut widths: Vec<f64> = Vec::with_capacity(segments.len());
+        let ui = Rc::downgrade(self);
+        let ticker = Rc::downgrade(ticker);
+        glib::timeout_add_local(Duration::from_millis(100), move || {
+            let (ui, ticker) = match (ui.upgrade(), ticker.upgrade()) {
+                (Some(ui), Some(ticker)) => (ui, ticker),
+                // Dropping the receiver stops the watcher thread
+                _ => return glib::ControlFlow::Break,
+            };
+            while let Ok(vis) = rx.try_recv() {
+                ticker.fullscreen.set(vis == hyprland::TickerVisibility::Hidden);
+                ticker.update_visibility(ui.user_hidden.get());
+            }
+            glib::ControlFlow::Continue
+        });
+    }
 
-        for seg in &segments {
-            let w = match cr.text_extents(&seg.text) {
-                Ok(ext) => {
-                    if seg.icon.is_some() { icon_space + ext.x_advance() } else { ext.x_advance() }
+    /// Scroll or page a window's ticker on its frame clock, by the time
+    /// since the previous frame, drawing at most `animation.fps` times a
+    /// second.
+    fn animate(self: &Rc<Self>, ticker: &Rc<TickerWindow>) {
+        let (ui, weak) = (Rc::downgrade(self), Rc::downgrade(ticker));
+        let last_frame: Cell<Option<i64>> = Cell::new(None);
+        let last_draw = Cell::new(0);
+
+        ticker.drawing_area.add_tick_callback(move |area, clock| {
+            let (ui, ticker) = match (ui.upgrade(), weak.upgrade()) {
+                (Some(ui), Some(ticker)) => (ui, ticker),
+                _ => return glib::ControlFlow::Break,
+            };
+            // Frame times are in microseconds
+            let now = clock.frame_time();
+            let elapsed = last_frame.get().map_or(0.0, |last| (now - last) as f64 / 1e6);
+            last_frame.set(Some(now));
+
+            let config = ui.config.borrow();
+            let paused = ui.paused.get() || (config.mouse.pause_on_hover && ticker.hovered.get());
+            if !paused {
+                ticker.clock.set(ticker.clock.get() + Duration::from_secs_f64(elapsed));
+
+                let mut off = ticker.scroll_offset.borrow_mut();
+                *off += config.animation.scroll_speed * elapsed;
+
+                let cached = *ticker.cached_width.borrow();
+                if cached > 0.0 && *off >= cached {
+                    *off %= cached;
                 }
-                Err(_) => if seg.icon.is_some() { icon_space } else { 0.0 },
+            }
+
+            // While paused, redraw only to show new prices. Allow for
+            // jitter so `fps` equal to the refresh rate draws every frame.
+            let interval = if paused { 1_000_000 } else { 1_000_000 / config.animation.fps as i64 };
+            if now - last_draw.get() >= interval - interval / 10 {
+                last_draw.set(now);
+                area.queue_draw();
+            }
+            glib::ControlFlow::Continue
+        });
+    }
+
+    /// Pause while hovered, show a coin's details in a tooltip, and act
+    /// on clicks and the mouse wheel.
+    fn watch_pointer(self: &Rc<Self>, ticker: &Rc<TickerWindow>) {
+        let area = &ticker.drawing_area;
+
+        let motion = gtk4::EventControllerMotion::new();
+        let weak = Rc::downgrade(ticker);
+        motion.connect_enter(move |_, _, _| {
+            if let Some(ticker) = weak.upgrade() {
+                ticker.hovered.set(true);
+            }
+        });
+        let weak = Rc::downgrade(ticker);
+        motion.connect_leave(move |_| {
+            if let Some(ticker) = weak.upgrade() {
+                ticker.hovered.set(false);
+            }
+        });
+        area.add_controller(motion);
+
+        area.set_has_tooltip(true);
+        let (ui, weak) = (Rc::downgrade(self), Rc::downgrade(ticker));
+        area.connect_query_tooltip(move |_, x, _, _, tooltip| {
+            let (ui, ticker) = match (ui.upgrade(), weak.upgrade()) {
+                (Some(ui), Some(ticker)) => (ui, ticker),
+                _ => return false,
             };
-            widths.push(w);
-            total_width += w;
+            if !ui.config.borrow().mouse.tooltip {
+                return false;
+            }
+            let details = ticker.segment_at(x as f64)
+                .and_then(|symbol| ui.state.lock().ok()?.details(&symbol));
+            match details {
+                Some(text) => {
+                    tooltip.set_text(Some(&text));
+                    true
+                }
+                None => false,
+            }
+        });
+
+        let click = gtk4::GestureClick::new();
+        click.set_button(gdk::BUTTON_PRIMARY);
+        let (ui, weak) = (Rc::downgrade(self), Rc::downgrade(ticker));
+        click.connect_released(move |_, _, x, _| {
+            if let (Some(ui), Some(ticker)) = (ui.upgrade(), weak.upgrade()) {
+                if let Some(symbol) = ticker.segment_at(x) {
+                    ui.click(&symbol);
+                }
+            }
+        });
+        area.add_controller(click);
+
+        let wheel = gtk4::EventControllerScroll::new(gtk4::EventControllerScrollFlags::VERTICAL);
+        let (ui, weak) = (Rc::downgrade(self), Rc::downgrade(ticker));
+        wheel.connect_scroll(move |_, _, dy| {
+            if let (Some(ui), Some(ticker)) = (ui.upgrade(), weak.upgrade()) {
+                ui.wheel(&ticker, dy);
+            }
+            glib::Propagation::Stop
+        });
+        area.add_controller(wheel);
+    }
+
+    /// Run `mouse.on_click` for a clicked coin.
+    fn click(&self, symbol: &str) {
+        let config = self.config.borrow();
+        // The portfolio total has no page of its own
+        let coin = match config.coins.iter().find(|c| c.symbol == symbol) {
+            Some(coin) => coin,
+            None => return,
+        };
+        let (base, quote) = symbol.split_once('/').unwrap_or((symbol, ""));
+        let fill = |template: &str| {
+            template
+                .replace("{symbol}", symbol)
+                .replace("{base}", base)
+                .replace("{quote}", quote)
+                .replace("{source}", coin.source.name())
+        };
+
+        let result = match &config.mouse.on_click {
+            ClickAction::None => return,
+            ClickAction::Trade => open_uri(&coin.source.trade_url(symbol)),
+            ClickAction::Open(url) => open_uri(&fill(url)),
+            ClickAction::Command(command) => {
+                let command = fill(command);
+                let argv = ["sh", "-c", command.as_str()].map(std::ffi::OsStr::new);
+                gio::Subprocess::newv(&argv, gio::SubprocessFlags::NONE).map(drop)
+            }
+        };
+        if let Err(e) = result {
+            eprintln!("Warning: Click action failed: {}", e);
+        }
+    }
+
+    /// Nudge a window's ticker or change the scroll speed by `dy` wheel
+    /// steps; scrolling down moves forward or slows down. In the `page` and
+    /// `roll` modes a nudge moves by whole coins.
+    fn wheel(&self, ticker: &TickerWindow, dy: f64) {
+        let mut config = self.config.borrow_mut();
+        let step = config.mouse.wheel_step * dy;
+
+        match config.mouse.wheel {
+            WheelAction::None => {}
+            WheelAction::Nudge if matches!(config.animation.mode, AnimationMode::Page | AnimationMode::Roll) => {
+                let page = config.animation.page_duration.as_secs_f64();
+                let clock = ticker.clock.get().as_secs_f64() + page * dy;
+                ticker.clock.set(Duration::from_secs_f64(clock.max(0.0)));
+                ticker.drawing_area.queue_draw();
+            }
+            WheelAction::Nudge => {
+                let cached = *ticker.cached_width.borrow();
+                let mut off = ticker.scroll_offset.borrow_mut();
+                *off += step;
+                if cached > 0.0 {
+                    *off = off.rem_euclid(cached);
+                }
+                ticker.drawing_area.queue_draw();
+            }
+            WheelAction::Speed => {
+                config.animation.scroll_speed = (config.animation.scroll_speed - step).max(0.0);
+            }
         }
+    }
 
-        if total_width <= 0.0 {
-            return;
+    fn update_visibility(&self) {
+        for ticker in self.windows.borrow().iter() {
+            ticker.update_visibility(self.user_hidden.get());
         }
+    }
 
-        // Cache width for smooth scrolling
-        let mut cached = cached_draw.borrow_mut();
-        if *cached <= 0.0 || (*cached - total_width).abs() > 1.0 {
-            *cached = total_width;
+    /// Push the current coin list to the ticker state and price sources.
+    fn apply_coins(&self) {
+        let config = self.config.borrow();
+        if let Ok(mut state) = self.state.lock() {
+            state.apply_config(&config);
         }
-        let use_width = *cached;
-        drop(cached);
-
-        let effective_offset = offset % use_width;
-        let text_y = (height as f64 + config_draw.appearance.font_size) / 2.0;
-        let icon_y = (height as f64 - config_draw.appearance.icon_size as f64) / 2.0;
-
-        let draw_ticker = |start_x: f64| {
-            let mut x = start_x;
-            for (i, seg) in segments.iter().enumerate() {
-                let seg_width = widths[i];
-
-                if x + seg_width > 0.0 && x < width as f64 {
-                    if let Some(ref icon_name) = seg.icon {
-                        if let Some(surface) = icons_draw.get(icon_name) {
-                            let _ = cr.set_source_surface(surface, x, icon_y);
-                            let _ = cr.paint();
-                        }
-                    }
+        self.icons.borrow_mut().clear();
+        let _ = self.config_tx.send(config.clone());
+        for ticker in self.windows.borrow().iter() {
+            *ticker.cached_width.borrow_mut() = 0.0;
+            ticker.drawing_area.queue_draw();
+        }
+    }
 
-                    let text_x = if seg.icon.is_some() { x + icon_space } else { x };
-                    let color = match seg.direction {
-                        ticker::Direction::Up => config_draw.appearance.color_up,
-                        ticker::Direction::Down => config_draw.appearance.color_down,
-                        ticker::Direction::Neutral => config_draw.appearance.color_neutral,
-                    };
-                    cr.set_source_rgb(color.0, color.1, color.2);
-                    cr.move_to(text_x, text_y);
-                    let _ = cr.show_text(&seg.text);
-                }
-                x += seg_width;
+    /// Re-read the config file and apply what can change at runtime.
+    fn reload(self: &Rc<Self>) -> Result<(), String> {
+        let new = Config::try_load()?;
+        let old = self.config.replace(new.clone());
+
+        if new.monitor != old.monitor {
+            self.sync_windows();
+        }
+        for ticker in self.windows.borrow().iter() {
+            ticker.apply_config(&new);
+        }
+
+        if new.feeds() != old.feeds() || new.network != old.network {
+            self.apply_coins();
+        } else {
+            if let Ok(mut state) = self.state.lock() {
+                state.apply_config(&new);
+            }
+            self.icons.borrow_mut().clear();
+        }
+        Ok(())
+    }
+
+    /// Reload whenever the config file is saved.
+    fn watch_config(self: &Rc<Self>) {
+        let file = gio::File::for_path(Config::config_path());
+        let monitor = match file.monitor_file(gio::FileMonitorFlags::WATCH_MOVES, gio::Cancellable::NONE) {
+            Ok(monitor) => monitor,
+            Err(e) => {
+                eprintln!("Warning: Can't watch config for changes: {}", e);
+                return;
             }
         };
 
-        draw_ticker(-effective_offset);
-        let end = use_width - effective_offset;
-        if end < width as f64 {
-            draw_ticker(end);
-        }
-    });
+        let ui = Rc::downgrade(self);
+        monitor.connect_changed(move |_, _, _, event| {
+            use gio::FileMonitorEvent::*;
 
-    window.set_child(Some(&drawing_area));
+            // A deleted config keeps the running settings rather than reverting to defaults
+            if !matches!(event, Changed | ChangesDoneHint | Created | MovedIn | Renamed) {
+                return;
+            }
+            let ui = match ui.upgrade() {
+                Some(ui) => ui,
+                None => return,
+            };
 
-    // Animation timer
-    let scroll_timer = Rc::clone(&scroll_offset);
-    let cached_timer = Rc::clone(&cached_width);
-    let drawing_timer = drawing_area.clone();
-    let fps = config.animation.fps;
-    let speed = config.animation.scroll_speed;
+            // Editors save in several steps; reload once they've settled
+            if let Some(id) = ui.pending_reload.take() {
+                id.remove();
+            }
+            let weak = Rc::downgrade(&ui);
+            let id = glib::timeout_add_local_once(RELOAD_DELAY, move || {
+                if let Some(ui) = weak.upgrade() {
+                    ui.pending_reload.take();
+                    if let Err(e) = ui.reload() {
+                        eprintln!("Warning: Config not reloaded: {}", e);
+                    }
+                }
+            });
+            ui.pending_reload.set(Some(id));
+        });
 
-    glib::timeout_add_local(Duration::from_millis(1000 / fps as u64), move || {
-        let mut off = scroll_timer.borrow_mut();
-        *off += speed / fps as f64;
+        *self.config_monitor.borrow_mut() = Some(monitor);
+    }
 
-        let cached = *cached_timer.borrow();
-        if cached > 0.0 && *off >= cached {
-            *off -= cached;
+    /// Run a control socket command, returning the reply for the client.
+    fn handle(self: &Rc<Self>, command: control::Command) -> String {
+        use control::Command;
+
+        match command {
+            Command::Pause => self.paused.set(true),
+            Command::Resume => self.paused.set(false),
+            Command::TogglePause => self.paused.set(!self.paused.get()),
+            Command::Show => {
+                self.user_hidden.set(false);
+                self.update_visibility();
+            }
+            Command::Hide => {
+                self.user_hidden.set(true);
+                self.update_visibility();
+            }
+            Command::ToggleVisible => {
+                self.user_hidden.set(!self.user_hidden.get());
+                self.update_visibility();
+            }
+            Command::Add { symbol, source } => {
+                let source = match source.as_deref().map(config::Source::from_name) {
+                    None => config::Source::Kraken,
+                    Some(Some(source)) => source,
+                    Some(None) => return format!("error: unknown source '{}'", source.unwrap_or_default()),
+                };
+                if let Err(e) = self.config.borrow_mut().add_coin(&symbol, source) {
+                    return format!("error: {}", e);
+                }
+                self.apply_coins();
+            }
+            Command::Remove { symbol } => {
+                if let Err(e) = self.config.borrow_mut().remove_coin(&symbol) {
+                    return format!("error: {}", e);
+                }
+                self.apply_coins();
+            }
+            Command::Speed { value, relative } => {
+                let mut config = self.config.borrow_mut();
+                let speed = if relative { config.animation.scroll_speed + value } else { value };
+                config.animation.scroll_speed = speed.max(0.0);
+            }
+            Command::Reload => {
+                if let Err(e) = self.reload() {
+                    return format!("error: {}", e);
+                }
+            }
+            Command::Prices => {
+                return match self.state.lock() {
+                    Ok(state) => state.prices_json().to_string(),
+                    Err(_) => "error: ticker state unavailable".to_string(),
+                };
+            }
         }
 
-        drawing_timer.queue_draw();
-        glib::ControlFlow::Continue
-    });
+        "ok".to_string()
+    }
+}
 
-    // WebSocket connection
-    let state_ws = Arc::clone(&state);
-    let config_ws = (*config).clone();
-    std::thread::spawn(move || {
-        websocket::run(&state_ws, &config_ws);
-    });
+/// Open a URL in the default browser.
+fn open_uri(uri: &str) -> Result<(), glib::Error> {
+    gio::AppInfo::launch_default_for_uri(uri, None::<&gio::AppLaunchContext>)
+}
 
-    window.present();
+/// A layer-shell window showing the ticker on one output.
+struct TickerWindow {
+    /// Output the window is pinned to; `None` leaves it to the compositor.
+    monitor: Option<gdk::Monitor>,
+    window: ApplicationWindow,
+    drawing_area: DrawingArea,
+    scroll_offset: Rc<RefCell<f64>>,
+    cached_width: Rc<RefCell<f64>>,
+    /// Time spent animating, which picks the coin in `page` and `roll`.
+    clock: Rc<Cell<Duration>>,
+    /// Segments as last drawn, for hit-testing.
+    hit_boxes: Rc<RefCell<HitBoxes>>,
+    /// The pointer is over the window.
+    hovered: Cell<bool>,
+    /// Hidden because a fullscreen window is on this output.
+    fullscreen: Cell<bool>,
+}
 
-    // Hyprland fullscreen detection
-    if let Some(ref monitor) = config.monitor {
-        let (tx, rx) = std::sync::mpsc::channel();
-        hyprland::watch_fullscreen(monitor.clone(), tx);
+impl TickerWindow {
+    /// Create and show a ticker window, on `monitor` if given.
+    fn new(ui: &Ui, monitor: Option<gdk::Monitor>) -> Self {
+        let config = ui.config.borrow();
+        let connector = monitor.as_ref().and_then(|m| m.connector());
+        let position = config.position_for(connector.as_deref());
+
+        let window = ApplicationWindow::builder()
+            .application(&ui.app)
+            .decorated(false)
+            .build();
+
+        // Layer shell setup
+        window.init_layer_shell();
+        window.set_layer(Layer::Overlay);
+        if let Some(ref monitor) = monitor {
+            window.set_monitor(monitor);
+        }
+        window.set_namespace("waybar-crypto-ticker");
+        window.set_keyboard_mode(gtk4_layer_shell::KeyboardMode::None);
+
+        // Drawing area
+        let drawing_area = DrawingArea::new();
+        apply_position(&window, &drawing_area, position);
+        drop(config);
+
+        let scroll_offset = Rc::new(RefCell::new(0.0f64));
+        let cached_width = Rc::new(RefCell::new(0.0f64));
+        let clock = Rc::new(Cell::new(Duration::ZERO));
+        let hit_boxes = Rc::new(RefCell::new(Vec::new()));
+
+        let state_draw = Arc::clone(&ui.state);
+        let scroll_draw = Rc::clone(&scroll_offset);
+        let icons_draw = Rc::clone(&ui.icons);
+        let cached_draw = Rc::clone(&cached_width);
+        let clock_draw = Rc::clone(&clock);
+        let config_draw = Rc::clone(&ui.config);
+        let monitor_draw = monitor.clone();
+        let hits_draw = Rc::clone(&hit_boxes);
+
+        // This output's segments as of `revision` and `flash_revision`, and
+        // the strip drawn from them
+        let segments_draw: RefCell<Vec<ticker::Segment>> = RefCell::new(Vec::new());
+        let revision_draw: Cell<Option<(u64, u64)>> = Cell::new(None);
+        let strip_draw: RefCell<Option<Strip>> = RefCell::new(None);
+        let connection_draw = Cell::new(ConnectionState::Connecting);
+        let stats_draw = ui.frame_stats.then(|| {
+            let label = connector.as_deref().unwrap_or("default output");
+            RefCell::new(FrameStats::new(label))
+        });
 
-        let window_vis = window.clone();
-        glib::timeout_add_local(Duration::from_millis(100), move || {
-            while let Ok(vis) = rx.try_recv() {
-                window_vis.set_visible(vis == hyprland::TickerVisibility::Visible);
+        drawing_area.set_draw_func(move |area, cr, width, height| {
+            let started = Instant::now();
+            let mut rendered = false;
+            let mut flashes_changed = false;
+
+            'draw: {
+                let config_draw = config_draw.borrow();
+
+                // Copy the segments only when they've changed
+                if let Ok(state) = state_draw.try_lock() {
+                    connection_draw.set(state.connection_state());
+                    let revisions = (state.revision(), state.flash_revision());
+                    if revision_draw.get() != Some(revisions) {
+                        revision_draw.set(Some(revisions));
+                        flashes_changed = true;
+                        // Only this output's coins, if it has its own list
+                        let connector = monitor_draw.as_ref().and_then(|m| m.connector());
+                        *segments_draw.borrow_mut() = match config_draw.coins_for(connector.as_deref()) {
+                            Some(symbols) => ticker::select_segments(&state.segments, symbols),
+                            None => state.segments.clone(),
+                        };
+                    }
+                }
+                let segments = segments_draw.borrow();
+                let connection = connection_draw.get();
+
+                // Clear background
+                cr.set_operator(gtk4::cairo::Operator::Clear);
+                let _ = cr.paint();
+                cr.set_operator(gtk4::cairo::Operator::Over);
+
+                if segments.is_empty() {
+                    hits_draw.borrow_mut().clear();
+                    let font = pango::FontDescription::from_string(&config_draw.appearance.font);
+                    let layout = strip::text_layout(cr, &font, match connection {
+                        ConnectionState::Live | ConnectionState::Connecting => "Connecting...",
+                        ConnectionState::Stale => "Waiting for prices...",
+                        ConnectionState::BackingOff => "Reconnecting...",
+                    });
+                    let c = config_draw.appearance.color_neutral;
+                    cr.set_source_rgb(c.0, c.1, c.2);
+                    let baseline_y = strip::baseline(cr, &font, height as f64);
+                    cr.move_to(10.0, baseline_y - strip::pango_units(layout.baseline()));
+                    pangocairo::functions::show_layout(cr, &layout);
+                    break 'draw;
+                }
+
+                // The output's fractional scale, so the strip is drawn 1:1
+                let scale = area.native()
+                    .and_then(|native| native.surface())
+                    .map_or(area.scale_factor() as f64, |surface| surface.scale());
+
+                // Re-render the strip if the segments, size or scale changed,
+                // otherwise just pick up new flashes
+                let (revision, _) = revision_draw.get().unwrap_or_default();
+                let key = StripKey::new(revision, height, scale);
+                let mut strip = strip_draw.borrow_mut();
+                match strip.as_mut() {
+                    Some(strip) if strip.is_current(&key) => {
+                        if flashes_changed {
+                            strip.show_flashes(&segments, &config_draw.appearance);
+                        }
+                    }
+                    _ => {
+                        let mut icons = icons_draw.borrow_mut();
+                        let icons = icons.entry(scale_key(scale)).or_insert_with(|| load_icons(&config_draw, scale));
+                        *strip = Strip::render(&segments, key, &config_draw.appearance, icons);
+                        rendered = true;
+                    }
+                }
+                let strip = match strip.as_ref() {
+                    Some(strip) => strip,
+                    None => break 'draw,
+                };
+
+                // Cache width for smooth scrolling
+                let mut cached = cached_draw.borrow_mut();
+                if *cached <= 0.0 || (*cached - strip.width).abs() > 1.0 {
+                    *cached = strip.width;
+                }
+                drop(cached);
+
+                let animation = &config_draw.animation;
+                let width = width as f64;
+                *hits_draw.borrow_mut() = match animation.mode {
+                    AnimationMode::Scroll => strip.draw_scroll(cr, *scroll_draw.borrow(), width),
+                    AnimationMode::Static => strip.draw_static(cr, width),
+                    AnimationMode::Page | AnimationMode::Roll => strip.draw_paged(
+                        cr,
+                        clock_draw.get(),
+                        animation.page_duration,
+                        animation.transition,
+                        animation.mode == AnimationMode::Roll,
+                        width,
+                    ),
+                };
+
+                // Status dot in the corner while the feed is unhealthy
+                if connection != ConnectionState::Live {
+                    let c = match connection {
+                        ConnectionState::BackingOff => config_draw.appearance.color_down,
+                        _ => config_draw.appearance.color_neutral,
+                    };
+                    cr.set_source_rgb(c.0, c.1, c.2);
+                    cr.arc(width - 5.0, 5.0, 2.5, 0.0, 2.0 * std::f64::consts::PI);
+                    let _ = cr.fill();
+                }
+            }
+
+            if let Some(stats) = &stats_draw {
+                stats.borrow_mut().record(started.elapsed(), rendered);
             }
-            glib::ControlFlow::Continue
         });
+
+        // Re-render the strip and icons when moved to an output with another scale
+        drawing_area.connect_scale_factor_notify(|area| area.queue_draw());
+        let area = drawing_area.downgrade();
+        window.connect_realize(move |window| {
+            let area = area.clone();
+            if let Some(surface) = window.surface() {
+                surface.connect_scale_notify(move |_| {
+                    if let Some(area) = area.upgrade() {
+                        area.queue_draw();
+                    }
+                });
+            }
+        });
+
+        window.set_child(Some(&drawing_area));
+        window.present();
+
+        Self {
+            monitor,
+            window,
+            drawing_area,
+            scroll_offset,
+            cached_width,
+            clock,
+            hit_boxes,
+            hovered: Cell::new(false),
+            fullscreen: Cell::new(false),
+        }
+    }
+
+    /// Symbol of the coin or total drawn at `x`, if any.
+    fn segment_at(&self, x: f64) -> Option<String> {
+        self.hit_boxes.borrow().iter()
+            .find(|(range, _)| range.contains(&x))
+            .and_then(|(_, symbol)| symbol.clone())
+    }
+
+    /// Connector name of the window's output, e.g. `DP-3`.
+    fn connector(&self) -> Option<String> {
+        self.monitor.as_ref().and_then(|m| m.connector()).map(String::from)
+    }
+
+    /// Move and resize the window after a config change.
+    fn apply_config(&self, config: &Config) {
+        let position = config.position_for(self.connector().as_deref());
+        apply_position(&self.window, &self.drawing_area, position);
+        self.drawing_area.queue_draw();
+    }
+
+    fn update_visibility(&self, user_hidden: bool) {
+        self.window.set_visible(!user_hidden && !self.fullscreen.get());
     }
 }
@@ -0,0 +1,575 @@
+//! Local mock of Kraken's v2 WebSocket and REST ticker/OHLC APIs.
+//!
+//! Serves both protocols on a single port so the overlay can run offline,
+//! e.g. for demos and screenshots. Prices follow a random walk, or replay a
+//! TOML script of steps when `--script` is given; a step can also drop
+//! every WebSocket, to watch the client reconnect. Point the client at it
+//! with the `kraken_ws` / `kraken_rest` overrides in `[network]`.
+
+use crate::cli::MockOptions;
+use crate::config::{Config, Source};
+use crate::kraken::ws_to_rest_symbol;
+use crate::websocket::BoxError;
+use futures_util::{SinkExt, StreamExt};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tokio::time::Duration;
+use tokio_tungstenite::tungstenite::Message;
+
+/// Simulated market state for one symbol.
+#[derive(Clone)]
+struct MockTicker {
+    last: f64,
+    open: f64,
+    low: f64,
+    high: f64,
+    volume: f64,
+}
+
+impl MockTicker {
+    fn new(price: f64) -> Self {
+        Self {
+            last: price,
+            open: price,
+            low: price,
+            high: price,
+            volume: 0.0,
+        }
+    }
+
+    fn trade(&mut self, price: f64, qty: f64) {
+        self.last = price;
+        self.low = self.low.min(price);
+        self.high = self.high.max(price);
+        self.volume += qty;
+    }
+}
+
+type Book = Arc<Mutex<HashMap<String, MockTicker>>>;
+
+/// A change broadcast to every WebSocket client.
+#[derive(Debug, Clone)]
+enum Update {
+    /// A symbol's ticker changed.
+    Ticker(String),
+    /// Close every connection.
+    Disconnect,
+}
+
+/// Scripted feed loaded from `--script`. Steps replay in order and loop.
+#[derive(Deserialize)]
+struct Script {
+    steps: Vec<ScriptStep>,
+}
+
+/// Trades `symbol` at `last`, or with `disconnect` drops every WebSocket.
+#[derive(Deserialize)]
+struct ScriptStep {
+    symbol: Option<String>,
+    last: Option<f64>,
+    #[serde(default)]
+    disconnect: bool,
+    #[serde(default = "default_delay_ms")]
+    delay_ms: u64,
+}
+
+fn default_delay_ms() -> u64 {
+    500
+}
+
+/// Rough starting prices so the demo looks plausible.
+fn initial_price(symbol: &str) -> f64 {
+    match symbol.split('/').next().unwrap_or(symbol) {
+        "BTC" => 67000.0,
+        "ETH" => 3500.0,
+        "SOL" => 150.0,
+        "AVAX" => 35.0,
+        "ADA" => 0.45,
+        "XRP" => 0.52,
+        "DOGE" => 0.15,
+        "SNEK" => 0.0025,
+        "EUR" => 1.08,
+        "GBP" => 1.27,
+        _ => 1.0,
+    }
+}
+
+fn load_script(path: &Path) -> Result<Script, BoxError> {
+    let contents = std::fs::read_to_string(path)?;
+    let script: Script = toml::from_str(&contents)?;
+    if script.steps.is_empty() {
+        return Err("script has no steps".into());
+    }
+    for (i, step) in script.steps.iter().enumerate() {
+        let trades = step.symbol.is_some() && step.last.is_some();
+        if !trades && !step.disconnect {
+            return Err(format!("step {} needs symbol and last, or disconnect", i + 1).into());
+        }
+    }
+    Ok(script)
+}
+
+/// Run the mock exchange until the process is killed.
+#[tokio::main]
+pub async fn run(config: &Config, options: MockOptions) -> Result<(), BoxError> {
+    let script = match &options.script {
+        Some(path) => Some(load_script(path)?),
+        None => None,
+    };
+
+    let book: Book = Arc::new(Mutex::new(
+        config.feeds().into_iter()
+            .filter(|(source, _)| *source == Source::Kraken)
+            .map(|(_, symbol)| (symbol.clone(), MockTicker::new(initial_price(&symbol))))
+            .collect(),
+    ));
+
+    let listener = TcpListener::bind(("127.0.0.1", options.port)).await?;
+    let addr = listener.local_addr()?;
+    println!("Mock Kraken exchange listening on {}", addr);
+    println!("Point the ticker at it with:\n");
+    println!("[network]");
+    println!("kraken_ws = \"ws://{}/v2\"", addr);
+    println!("kraken_rest = \"http://{}/0/public/Ticker\"", addr);
+
+    let (updates, _) = broadcast::channel(256);
+
+    let feed_book = Arc::clone(&book);
+    let feed_updates = updates.clone();
+    tokio::spawn(async move {
+        match script {
+            Some(script) => scripted_feed(feed_book, feed_updates, script).await,
+            None => random_walk_feed(feed_book, feed_updates).await,
+        }
+    });
+
+    serve(listener, book, updates).await
+}
+
+/// Accept WebSocket and REST clients, passing `updates` on to the
+/// WebSocket subscribers.
+async fn serve(listener: TcpListener, book: Book, updates: broadcast::Sender<Update>) -> Result<(), BoxError> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let book = Arc::clone(&book);
+        let updates = updates.subscribe();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, book, updates).await {
+                eprintln!("Mock exchange: {}", e);
+            }
+        });
+    }
+}
+
+async fn random_walk_feed(book: Book, updates: broadcast::Sender<Update>) {
+    let mut rng = StdRng::from_entropy();
+    let mut interval = tokio::time::interval(Duration::from_millis(500));
+
+    loop {
+        interval.tick().await;
+
+        let mut moved = Vec::new();
+        match book.lock() {
+            Ok(mut book) => {
+                for (symbol, ticker) in book.iter_mut() {
+                    if rng.gen_bool(0.5) {
+                        let step = rng.gen_range(-0.002..0.002);
+                        ticker.trade(ticker.last * (1.0 + step), rng.gen_range(0.01..2.0));
+                        moved.push(symbol.clone());
+                    }
+                }
+            }
+            Err(_) => return,
+        }
+
+        for symbol in moved {
+            let _ = updates.send(Update::Ticker(symbol));
+        }
+    }
+}
+
+async fn scripted_feed(book: Book, updates: broadcast::Sender<Update>, script: Script) {
+    for step in script.steps.iter().cycle() {
+        tokio::time::sleep(Duration::from_millis(step.delay_ms)).await;
+
+        if let (Some(symbol), Some(last)) = (&step.symbol, step.last) {
+            if let Ok(mut book) = book.lock() {
+                book.entry(symbol.clone())
+                    .or_insert_with(|| MockTicker::new(last))
+                    .trade(last, 1.0);
+            }
+            let _ = updates.send(Update::Ticker(symbol.clone()));
+        }
+        if step.disconnect {
+            let _ = updates.send(Update::Disconnect);
+        }
+    }
+}
+
+/// Dispatch on the request head: WebSocket upgrades go to the v2 feed,
+/// everything else is treated as a REST call.
+async fn handle_connection(
+    stream: TcpStream,
+    book: Book,
+    updates: broadcast::Receiver<Update>,
+) -> Result<(), BoxError> {
+    let head = peek_request_head(&stream).await?;
+
+    if head.to_ascii_lowercase().contains("upgrade: websocket") {
+        serve_websocket(stream, book, updates).await
+    } else {
+        serve_rest(stream, head.len(), &book).await
+    }
+}
+
+/// Peek at the request headers without consuming them, so the WebSocket
+/// handshake can still read the full request.
+async fn peek_request_head(stream: &TcpStream) -> Result<String, BoxError> {
+    let mut buf = [0u8; 4096];
+
+    loop {
+        let n = stream.peek(&mut buf).await?;
+        if n == 0 {
+            return Err("connection closed before request".into());
+        }
+
+        let data = &buf[..n];
+        if let Some(end) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+            return Ok(String::from_utf8_lossy(&data[..end + 4]).into_owned());
+        }
+        if n == buf.len() {
+            return Err("request headers too large".into());
+        }
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+async fn serve_rest(mut stream: TcpStream, head_len: usize, book: &Book) -> Result<(), BoxError> {
+    let mut head = vec![0u8; head_len];
+    stream.read_exact(&mut head).await?;
+    let head = String::from_utf8_lossy(&head);
+
+    let target = head.split_whitespace().nth(1).unwrap_or("/");
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    let (status, body) = if path.ends_with("/public/Ticker") {
+        let pairs: Vec<&str> = query.split('&')
+            .filter_map(|kv| kv.strip_prefix("pair="))
+            .flat_map(|v| v.split(','))
+            .collect();
+        ("200 OK", rest_ticker_body(&pairs, book))
+    } else if path.ends_with("/public/OHLC") {
+        let param = |name: &str| query.split('&')
+            .find_map(|kv| kv.strip_prefix(name)?.strip_prefix('='));
+        let pair = param("pair").unwrap_or("");
+        let interval = param("interval").and_then(|v| v.parse().ok()).unwrap_or(1u64);
+        let since = param("since").and_then(|v| v.parse().ok()).unwrap_or(0u64);
+        ("200 OK", rest_ohlc_body(pair, interval, since, book))
+    } else {
+        ("404 Not Found", json!({ "error": ["EGeneral:Unknown method"] }))
+    };
+
+    let body = body.to_string();
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body,
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Build a `/0/public/Ticker` response for the requested REST pair names.
+fn rest_ticker_body(pairs: &[&str], book: &Book) -> serde_json::Value {
+    let book = match book.lock() {
+        Ok(book) => book.clone(),
+        Err(_) => HashMap::new(),
+    };
+
+    // Like Kraken, fail the whole query if any pair is unknown
+    let known: Vec<String> = book.keys().map(|s| ws_to_rest_symbol(s)).collect();
+    if pairs.iter().any(|p| !known.iter().any(|k| k == p)) {
+        return json!({ "error": ["EQuery:Unknown asset pair"] });
+    }
+
+    let result: serde_json::Map<String, serde_json::Value> = book.iter()
+        .map(|(symbol, ticker)| (ws_to_rest_symbol(symbol), ticker))
+        .filter(|(rest, _)| pairs.is_empty() || pairs.contains(&rest.as_str()))
+        .map(|(rest, t)| {
+            (rest, json!({
+                "c": [t.last.to_string(), "1.0"],
+                "v": [t.volume.to_string(), t.volume.to_string()],
+                "l": [t.low.to_string(), t.low.to_string()],
+                "h": [t.high.to_string(), t.high.to_string()],
+                "o": t.open.to_string(),
+            }))
+        })
+        .collect();
+
+    json!({ "error": [], "result": result })
+}
+
+/// Build a `/0/public/OHLC` response: a synthetic walk from `since` that
+/// ends at the pair's current price.
+fn rest_ohlc_body(pair: &str, interval: u64, since: u64, book: &Book) -> serde_json::Value {
+    let last = match book.lock() {
+        Ok(book) => book.iter()
+            .find(|(symbol, _)| ws_to_rest_symbol(symbol) == pair)
+            .map(|(_, t)| t.last),
+        Err(_) => None,
+    };
+    let last = match last {
+        Some(last) => last,
+        None => return json!({ "error": ["EQuery:Unknown asset pair"] }),
+    };
+
+    let step = interval.max(1) * 60;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let count = (now.saturating_sub(since) / step).clamp(1, 720);
+
+    // Walk backwards from the live price so the history joins up with it
+    let mut rng = StdRng::from_entropy();
+    let mut price = last;
+    let mut closes = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        closes.push(price);
+        price *= 1.0 + rng.gen_range(-0.002..0.002);
+    }
+    closes.reverse();
+
+    let candles: Vec<serde_json::Value> = closes.iter().enumerate()
+        .map(|(i, close)| {
+            let time = now - (count - 1 - i as u64) * step;
+            let close = format!("{:.5}", close);
+            json!([time, close, close, close, close, close, "1.0", 1])
+        })
+        .collect();
+
+    json!({ "error": [], "result": { pair: candles, "last": now } })
+}
+
+/// Build a v2 `ticker` channel entry.
+fn ticker_json(symbol: &str, t: &MockTicker) -> serde_json::Value {
+    let spread = t.last * 0.0001;
+    let change = t.last - t.open;
+    json!({
+        "symbol": symbol,
+        "bid": t.last - spread,
+        "bid_qty": 1.0,
+        "ask": t.last + spread,
+        "ask_qty": 1.0,
+        "last": t.last,
+        "volume": t.volume,
+        "vwap": (t.low + t.high + t.last) / 3.0,
+        "low": t.low,
+        "high": t.high,
+        "change": change,
+        "change_pct": if t.open > 0.0 { change / t.open * 100.0 } else { 0.0 },
+    })
+}
+
+async fn serve_websocket(
+    stream: TcpStream,
+    book: Book,
+    mut updates: broadcast::Receiver<Update>,
+) -> Result<(), BoxError> {
+    let ws_stream = tokio_tungstenite::accept_async(stream).await?;
+    let (mut write, mut read) = ws_stream.split();
+    let mut subscribed: HashSet<String> = HashSet::new();
+    let mut heartbeat = tokio::time::interval(Duration::from_secs(1));
+
+    loop {
+        tokio::select! {
+            msg = read.next() => {
+                let text = match msg {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Ping(data))) => {
+                        write.send(Message::Pong(data)).await?;
+                        continue;
+                    }
+                    Some(Ok(Message::Close(_))) | None => return Ok(()),
+                    Some(Ok(_)) => continue,
+                    Some(Err(e)) => return Err(Box::new(e)),
+                };
+
+                for reply in handle_request(&text, &book, &mut subscribed) {
+                    write.send(Message::Text(reply.to_string())).await?;
+                }
+            }
+            update = updates.recv() => {
+                let symbol = match update {
+                    Ok(Update::Ticker(symbol)) => symbol,
+                    Ok(Update::Disconnect) => {
+                        let _ = write.send(Message::Close(None)).await;
+                        return Ok(());
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return Ok(()),
+                };
+                if !subscribed.contains(&symbol) {
+                    continue;
+                }
+
+                let ticker = book.lock().ok().and_then(|b| b.get(&symbol).cloned());
+                if let Some(ticker) = ticker {
+                    let msg = json!({
+                        "channel": "ticker",
+                        "type": "update",
+                        "data": [ticker_json(&symbol, &ticker)],
+                    });
+                    write.send(Message::Text(msg.to_string())).await?;
+                }
+            }
+            _ = heartbeat.tick() => {
+                write.send(Message::Text(json!({ "channel": "heartbeat" }).to_string())).await?;
+            }
+        }
+    }
+}
+
+/// Handle a client request frame, returning the frames to send back.
+fn handle_request(
+    text: &str,
+    book: &Book,
+    subscribed: &mut HashSet<String>,
+) -> Vec<serde_json::Value> {
+    let request: serde_json::Value = match serde_json::from_str(text) {
+        Ok(value) => value,
+        Err(_) => return vec![json!({ "error": "Malformed request", "success": false })],
+    };
+
+    match request["method"].as_str() {
+        Some("ping") => vec![json!({ "method": "pong", "req_id": request["req_id"] })],
+        Some("subscribe") if request["params"]["channel"] == "ticker" => {
+            let symbols: Vec<String> = request["params"]["symbol"].as_array()
+                .map(|a| a.iter().filter_map(|s| s.as_str().map(String::from)).collect())
+                .unwrap_or_default();
+
+            let mut replies = Vec::new();
+            let mut snapshot = Vec::new();
+
+            if let Ok(mut book) = book.lock() {
+                for symbol in symbols {
+                    let ticker = book.entry(symbol.clone())
+                        .or_insert_with(|| MockTicker::new(initial_price(&symbol)));
+                    snapshot.push(ticker_json(&symbol, ticker));
+
+                    replies.push(json!({
+                        "method": "subscribe",
+                        "result": { "channel": "ticker", "symbol": symbol, "snapshot": true },
+                        "success": true,
+                    }));
+                    subscribed.insert(symbol);
+                }
+            }
+
+            if request["params"]["snapshot"] != false && !snapshot.is_empty() {
+                replies.push(json!({ "channel": "ticker", "type": "snapshot", "data": snapshot }));
+            }
+            replies
+        }
+        _ => vec![json!({ "error": "Unsupported request", "success": false })],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kraken::Kraken;
+    use crate::ticker::TickerState;
+    use crate::websocket::{self, PriceSource};
+    use std::time::Instant;
+
+    /// Serve a mock with BTC/USD at 100 on an ephemeral port.
+    async fn start_mock() -> (std::net::SocketAddr, Book, broadcast::Sender<Update>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let book: Book = Arc::new(Mutex::new(HashMap::from([
+            ("BTC/USD".to_string(), MockTicker::new(100.0)),
+        ])));
+        let (updates, _) = broadcast::channel(16);
+        tokio::spawn(serve(listener, Arc::clone(&book), updates.clone()));
+        (addr, book, updates)
+    }
+
+    fn config_for(addr: std::net::SocketAddr) -> Config {
+        Config::parse(&format!(r#"
+            [network]
+            kraken_ws = "ws://{addr}/v2"
+            kraken_rest = "http://{addr}/0/public/Ticker"
+
+            [[coins]]
+            symbol = "BTC/USD"
+            name = "Bitcoin"
+            icon = "btc.svg"
+        "#)).unwrap()
+    }
+
+    /// Wait for BTC/USD's price and 24h open to reach `price` and `open`.
+    async fn wait_for_price(state: &Mutex<TickerState>, price: f64, open: f64) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            let prices = state.lock().unwrap().prices_json();
+            let coin = &prices["coins"][0];
+            if coin["price"] == price && coin["open_24h"] == open {
+                return;
+            }
+            assert!(Instant::now() < deadline, "timed out waiting for {}: {}", price, coin);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn kraken_streams_ticks_and_reconnects() {
+        let (addr, book, updates) = start_mock().await;
+        let config = config_for(addr);
+        let state = Arc::new(Mutex::new(TickerState::new(&config)));
+
+        let (_config_tx, config_rx) = tokio::sync::watch::channel(config);
+        let state_ws = Arc::clone(&state);
+        std::thread::spawn(move || websocket::run(&state_ws, config_rx));
+
+        // The subscribe snapshot
+        wait_for_price(&state, 100.0, 100.0).await;
+
+        // An update, with the open taken from the feed's 24h change
+        book.lock().unwrap().get_mut("BTC/USD").unwrap().trade(110.0, 1.0);
+        updates.send(Update::Ticker("BTC/USD".to_string())).unwrap();
+        wait_for_price(&state, 110.0, 100.0).await;
+
+        // A price the client can only see in a new snapshot, after reconnecting
+        updates.send(Update::Disconnect).unwrap();
+        book.lock().unwrap().get_mut("BTC/USD").unwrap().trade(120.0, 1.0);
+        wait_for_price(&state, 120.0, 100.0).await;
+    }
+
+    #[tokio::test]
+    async fn kraken_rest_lists_pairs_and_history() {
+        let (addr, _book, _updates) = start_mock().await;
+        let config = config_for(addr);
+        let listed = Kraken::new(vec!["BTC/USD".to_string()], &config.network);
+        let mixed = Kraken::new(vec!["BTC/USD".to_string(), "NOPE/USD".to_string()], &config.network);
+
+        let (unknown, history) = tokio::task::spawn_blocking(move || {
+            (mixed.unknown_symbols().unwrap(), listed.fetch_history(Duration::from_secs(3600)).unwrap())
+        }).await.unwrap();
+
+        assert_eq!(unknown, vec!["NOPE/USD".to_string()]);
+        let points = &history["BTC/USD"];
+        assert!(!points.is_empty());
+        assert_eq!(points.last().map(|(_, close)| *close), Some(100.0));
+    }
+}
@@ -0,0 +1,371 @@
+//! Outbound connection setup shared by all price sources.
+//!
+//! Applies the `[network]` config (proxy, timeouts, extra CA roots and user
+//! agent) to both the WebSocket connections and the blocking REST client.
+
+use crate::config::Network;
+use crate::websocket::BoxError;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::header::USER_AGENT;
+use tokio_tungstenite::{
+    client_async_tls_with_config, connect_async_tls_with_config, Connector, MaybeTlsStream,
+    WebSocketStream,
+};
+
+pub type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// User agent sent when the config doesn't set one.
+pub const DEFAULT_USER_AGENT: &str = concat!("waybar-crypto-ticker/", env!("CARGO_PKG_VERSION"));
+
+/// Build a blocking REST client from the network config.
+///
+/// Falls back to a default client if the config is unusable (e.g. a missing
+/// CA bundle), after logging why. Must not be called on an async thread.
+pub fn http_client(network: &Network) -> reqwest::blocking::Client {
+    build_http_client(network).unwrap_or_else(|e| {
+        eprintln!("Warning: Invalid network config, using defaults: {}", e);
+        reqwest::blocking::Client::new()
+    })
+}
+
+fn build_http_client(network: &Network) -> Result<reqwest::blocking::Client, BoxError> {
+    let mut builder = reqwest::blocking::Client::builder()
+        .user_agent(&network.user_agent)
+        .connect_timeout(network.connect_timeout)
+        .timeout(network.request_timeout);
+
+    if let Some(proxy) = &network.proxy {
+        builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+    }
+
+    if let Some(path) = &network.ca_bundle {
+        for cert in reqwest::Certificate::from_pem_bundle(&std::fs::read(path)?)? {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+
+    Ok(builder.build()?)
+}
+
+/// Open a WebSocket connection, going through the configured proxy if any.
+pub async fn connect_ws(url: &str, network: &Network) -> Result<WsStream, BoxError> {
+    let mut request = url.into_client_request()?;
+    request.headers_mut().insert(USER_AGENT, network.user_agent.parse()?);
+    let connector = tls_connector(network)?;
+
+    let connect = async {
+        let ws_stream = match &network.proxy {
+            Some(proxy) => {
+                let uri = request.uri();
+                let host = uri.host().ok_or("WebSocket URL has no host")?.to_string();
+                let default_port = if uri.scheme_str() == Some("wss") { 443 } else { 80 };
+                let port = uri.port_u16().unwrap_or(default_port);
+
+                let stream = proxy_connect(proxy, &host, port).await?;
+                client_async_tls_with_config(request, stream, None, connector).await?.0
+            }
+            None => connect_async_tls_with_config(request, None, false, connector).await?.0,
+        };
+        Ok::<_, BoxError>(ws_stream)
+    };
+
+    tokio::time::timeout(network.connect_timeout, connect).await
+        .map_err(|_| "connection timed out")?
+}
+
+/// TLS connector with the extra CA bundle added, or `None` for the defaults.
+fn tls_connector(network: &Network) -> Result<Option<Connector>, BoxError> {
+    let path = match &network.ca_bundle {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+
+    let pem = std::fs::read(path)?;
+    let mut builder = native_tls::TlsConnector::builder();
+    for cert in split_pem_bundle(&pem) {
+        builder.add_root_certificate(native_tls::Certificate::from_pem(cert.as_bytes())?);
+    }
+
+    Ok(Some(Connector::NativeTls(builder.build()?)))
+}
+
+/// Split a PEM bundle into individual certificates.
+fn split_pem_bundle(pem: &[u8]) -> Vec<String> {
+    const END: &str = "-----END CERTIFICATE-----";
+
+    String::from_utf8_lossy(pem)
+        .split_inclusive(END)
+        .filter(|block| block.contains(END))
+        .map(|block| block.trim_start().to_string())
+        .collect()
+}
+
+/// Open a TCP tunnel to `host:port` through an `http://` or `socks5://` proxy.
+async fn proxy_connect(proxy: &str, host: &str, port: u16) -> Result<TcpStream, BoxError> {
+    let proxy = url::Url::parse(proxy)?;
+    let proxy_host = proxy.host_str().ok_or("proxy URL has no host")?;
+    let credentials = match proxy.username() {
+        "" => None,
+        user => Some((user, proxy.password().unwrap_or(""))),
+    };
+
+    match proxy.scheme() {
+        "http" => {
+            let proxy_port = proxy.port().unwrap_or(8080);
+            let stream = TcpStream::connect((proxy_host, proxy_port)).await?;
+            http_connect(stream, host, port, credentials).await
+        }
+        "socks5" | "socks5h" => {
+            let proxy_port = proxy.port().unwrap_or(1080);
+            let stream = TcpStream::connect((proxy_host, proxy_port)).await?;
+            socks5_connect(stream, host, port, credentials).await
+        }
+        other => Err(format!("unsupported proxy scheme '{}' for WebSocket", other).into()),
+    }
+}
+
+/// Establish a tunnel with an HTTP `CONNECT` request.
+async fn http_connect(
+    mut stream: TcpStream,
+    host: &str,
+    port: u16,
+    credentials: Option<(&str, &str)>,
+) -> Result<TcpStream, BoxError> {
+    let mut request = format!("CONNECT {0}:{1} HTTP/1.1\r\nHost: {0}:{1}\r\n", host, port);
+    if let Some((user, pass)) = credentials {
+        let token = base64_encode(format!("{}:{}", user, pass).as_bytes());
+        request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", token));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    // Read the response head one byte at a time so nothing past it is consumed.
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() > 8192 {
+            return Err("proxy response too large".into());
+        }
+        head.push(stream.read_u8().await?);
+    }
+
+    let head = String::from_utf8_lossy(&head);
+    let status = head.split_whitespace().nth(1).unwrap_or("");
+    if status != "200" {
+        let line = head.lines().next().unwrap_or("");
+        return Err(format!("proxy refused CONNECT: {}", line).into());
+    }
+
+    Ok(stream)
+}
+
+/// Establish a tunnel with a SOCKS5 handshake (RFC 1928), resolving the
+/// target hostname on the proxy side.
+async fn socks5_connect(
+    mut stream: TcpStream,
+    host: &str,
+    port: u16,
+    credentials: Option<(&str, &str)>,
+) -> Result<TcpStream, BoxError> {
+    let host = socks5_field("hostname", host)?;
+    let credentials = match credentials {
+        Some((user, pass)) => Some((socks5_field("username", user)?, socks5_field("password", pass)?)),
+        None => None,
+    };
+
+    // Greeting: offer no-auth, plus username/password if we have credentials
+    match credentials {
+        Some(_) => stream.write_all(&[5, 2, 0, 2]).await?,
+        None => stream.write_all(&[5, 1, 0]).await?,
+    }
+
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply).await?;
+    match (reply[1], credentials) {
+        (0, _) => {}
+        (2, Some((user, pass))) => {
+            // Username/password sub-negotiation (RFC 1929)
+            let auth = [&[1][..], &user, &pass].concat();
+            stream.write_all(&auth).await?;
+
+            stream.read_exact(&mut reply).await?;
+            if reply[1] != 0 {
+                return Err("SOCKS5 proxy rejected credentials".into());
+            }
+        }
+        _ => return Err("SOCKS5 proxy offered no usable auth method".into()),
+    }
+
+    let connect = [&[5, 1, 0, 3][..], &host, &port.to_be_bytes()].concat();
+    stream.write_all(&connect).await?;
+
+    let mut head = [0u8; 4];
+    stream.read_exact(&mut head).await?;
+    if head[1] != 0 {
+        return Err(format!("SOCKS5 connect failed (reply code {})", head[1]).into());
+    }
+
+    // Skip the bound address, whose length depends on its type
+    let addr_len = match head[3] {
+        1 => 4,
+        4 => 16,
+        3 => stream.read_u8().await? as usize,
+        other => return Err(format!("SOCKS5 reply has unknown address type {}", other).into()),
+    };
+    let mut bound = vec![0u8; addr_len + 2];
+    stream.read_exact(&mut bound).await?;
+
+    Ok(stream)
+}
+
+/// A length-prefixed SOCKS5 field, which can hold at most 255 bytes.
+fn socks5_field(name: &str, value: &str) -> Result<Vec<u8>, BoxError> {
+    let len = u8::try_from(value.len())
+        .map_err(|_| format!("SOCKS5 {} is longer than 255 bytes", name))?;
+    Ok([&[len][..], value.as_bytes()].concat())
+}
+
+fn base64_encode(input: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::with_capacity(input.len().div_ceil(3) * 4);
+    for chunk in input.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::future::Future;
+    use tokio::net::TcpListener;
+    use tokio::task::JoinHandle;
+
+    /// Connect to a fake proxy that runs `script` on the connection and
+    /// returns what it read.
+    async fn fake_proxy<F, Fut>(script: F) -> (TcpStream, JoinHandle<Vec<u8>>)
+    where
+        F: FnOnce(TcpStream) -> Fut + Send + 'static,
+        Fut: Future<Output = Vec<u8>> + Send,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            script(stream).await
+        });
+        (TcpStream::connect(addr).await.unwrap(), server)
+    }
+
+    /// Read an HTTP request head, then send `reply`.
+    async fn http_proxy(mut stream: TcpStream, reply: &'static str) -> Vec<u8> {
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            head.push(stream.read_u8().await.unwrap());
+        }
+        stream.write_all(reply.as_bytes()).await.unwrap();
+        head
+    }
+
+    async fn read_exactly(stream: &mut TcpStream, len: usize) -> Vec<u8> {
+        let mut buf = vec![0u8; len];
+        stream.read_exact(&mut buf).await.unwrap();
+        buf
+    }
+
+    #[test]
+    fn base64_pads_partial_chunks() {
+        assert_eq!(base64_encode(b""), "");
+        assert_eq!(base64_encode(b"f"), "Zg==");
+        assert_eq!(base64_encode(b"fo"), "Zm8=");
+        assert_eq!(base64_encode(b"foo"), "Zm9v");
+        assert_eq!(base64_encode(b"user:pass"), "dXNlcjpwYXNz");
+    }
+
+    #[test]
+    fn splits_pem_bundles() {
+        let bundle = b"-----BEGIN CERTIFICATE-----\nAAA\n-----END CERTIFICATE-----\n\n\
+            -----BEGIN CERTIFICATE-----\nBBB\n-----END CERTIFICATE-----\ntrailing junk\n";
+        assert_eq!(split_pem_bundle(bundle), [
+            "-----BEGIN CERTIFICATE-----\nAAA\n-----END CERTIFICATE-----",
+            "-----BEGIN CERTIFICATE-----\nBBB\n-----END CERTIFICATE-----",
+        ]);
+        assert!(split_pem_bundle(b"not a certificate").is_empty());
+    }
+
+    #[tokio::test]
+    async fn http_connect_tunnels_with_credentials() {
+        let (stream, proxy) = fake_proxy(|stream| {
+            http_proxy(stream, "HTTP/1.1 200 Connection established\r\n\r\ntunnel")
+        }).await;
+
+        let mut tunnel = http_connect(stream, "ws.kraken.com", 443, Some(("user", "pass"))).await.unwrap();
+        let mut rest = String::new();
+        tunnel.read_to_string(&mut rest).await.unwrap();
+
+        // Bytes after the response head belong to the tunnel
+        assert_eq!(rest, "tunnel");
+        assert_eq!(
+            String::from_utf8(proxy.await.unwrap()).unwrap(),
+            "CONNECT ws.kraken.com:443 HTTP/1.1\r\nHost: ws.kraken.com:443\r\n\
+             Proxy-Authorization: Basic dXNlcjpwYXNz\r\n\r\n",
+        );
+    }
+
+    #[tokio::test]
+    async fn http_connect_reports_refusals() {
+        let (stream, _proxy) = fake_proxy(|stream| {
+            http_proxy(stream, "HTTP/1.1 407 Proxy Authentication Required\r\n\r\n")
+        }).await;
+
+        let error = http_connect(stream, "ws.kraken.com", 443, None).await.unwrap_err();
+        assert_eq!(error.to_string(), "proxy refused CONNECT: HTTP/1.1 407 Proxy Authentication Required");
+    }
+
+    #[tokio::test]
+    async fn socks5_connect_authenticates_and_tunnels() {
+        let (stream, proxy) = fake_proxy(|mut stream| async move {
+            let mut read = read_exactly(&mut stream, 4).await;
+            stream.write_all(&[5, 2]).await.unwrap();
+            read.extend(read_exactly(&mut stream, 11).await);
+            stream.write_all(&[1, 0]).await.unwrap();
+            read.extend(read_exactly(&mut stream, 20).await);
+            // Bound to a hostname, which has a length prefix to skip
+            stream.write_all(&[5, 0, 0, 3, 5, b'p', b'r', b'o', b'x', b'y', 1, 187]).await.unwrap();
+            stream.write_all(b"tunnel").await.unwrap();
+            read
+        }).await;
+
+        let mut tunnel = socks5_connect(stream, "ws.kraken.com", 443, Some(("user", "pass"))).await.unwrap();
+        let mut rest = String::new();
+        tunnel.read_to_string(&mut rest).await.unwrap();
+        assert_eq!(rest, "tunnel");
+
+        let expected = [
+            &[5, 2, 0, 2][..],
+            &[1, 4], b"user", &[4], b"pass",
+            &[5, 1, 0, 3, 13], b"ws.kraken.com", &[1, 187],
+        ].concat();
+        assert_eq!(proxy.await.unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn socks5_connect_rejects_overlong_fields() {
+        let (stream, _proxy) = fake_proxy(|_| async { Vec::new() }).await;
+        let host = "a".repeat(256);
+
+        let error = socks5_connect(stream, &host, 443, None).await.unwrap_err();
+        assert_eq!(error.to_string(), "SOCKS5 hostname is longer than 255 bytes");
+    }
+}
@@ -0,0 +1,243 @@
+//! Desktop notifications over D-Bus (`org.freedesktop.Notifications`).
+//!
+//! Uses gio's D-Bus client on the session bus named by
+//! `DBUS_SESSION_BUS_ADDRESS`, so it can be pointed at a private bus (e.g.
+//! under `dbus-run-session`) for testing.
+
+use crate::alerts::AlertEvent;
+use crate::config::Config;
+use gtk4::gio;
+use gtk4::glib;
+use gtk4::prelude::*;
+use std::collections::HashMap;
+
+const BUS_NAME: &str = "org.freedesktop.Notifications";
+const OBJECT_PATH: &str = "/org/freedesktop/Notifications";
+const INTERFACE: &str = "org.freedesktop.Notifications";
+const APP_NAME: &str = "waybar-crypto-ticker";
+
+/// How long notifications stay up, in milliseconds (-1 = server default).
+const EXPIRE_TIMEOUT: i32 = -1;
+
+/// Sends alert notifications on the session bus.
+pub struct Notifier {
+    connection: Option<gio::DBusConnection>,
+}
+
+impl Notifier {
+    /// Connect to the session bus, logging (but tolerating) a missing one.
+    pub fn new() -> Self {
+        let connection = match gio::bus_get_sync(gio::BusType::Session, gio::Cancellable::NONE) {
+            Ok(connection) => Some(connection),
+            Err(e) => {
+                eprintln!("Warning: No D-Bus session bus, alerts won't notify: {}", e);
+                None
+            }
+        };
+
+        Self { connection }
+    }
+
+    /// Send a notification without blocking; failures are logged.
+    pub fn send(&self, event: &AlertEvent) {
+        let connection = match &self.connection {
+            Some(connection) => connection,
+            None => return,
+        };
+
+        let symbol = event.symbol.clone();
+        connection.call(
+            Some(BUS_NAME),
+            OBJECT_PATH,
+            INTERFACE,
+            "Notify",
+            Some(&notify_params(event)),
+            Some(glib::VariantTy::new("(u)").expect("valid variant type")),
+            gio::DBusCallFlags::NONE,
+            -1,
+            gio::Cancellable::NONE,
+            move |result| {
+                if let Err(e) = result {
+                    eprintln!("Warning: Failed to send alert for {}: {}", symbol, e);
+                }
+            },
+        );
+    }
+
+    /// Send a notification from a thread with no GLib main loop to run
+    /// `send`'s reply callback, waiting for the reply; failures are logged.
+    pub fn send_blocking(&self, event: &AlertEvent) {
+        if self.connection.is_none() {
+            return;
+        }
+        if let Err(e) = self.send_sync(event) {
+            eprintln!("Warning: Failed to send alert for {}: {}", event.symbol, e);
+        }
+    }
+
+    /// Send a notification and wait for the server's reply, returning the
+    /// notification id.
+    pub fn send_sync(&self, event: &AlertEvent) -> Result<u32, glib::Error> {
+        let connection = self.connection.as_ref().ok_or_else(|| {
+            glib::Error::new(gio::IOErrorEnum::NotConnected, "no D-Bus session bus")
+        })?;
+
+        let reply = connection.call_sync(
+            Some(BUS_NAME),
+            OBJECT_PATH,
+            INTERFACE,
+            "Notify",
+            Some(&notify_params(event)),
+            Some(glib::VariantTy::new("(u)").expect("valid variant type")),
+            gio::DBusCallFlags::NONE,
+            -1,
+            gio::Cancellable::NONE,
+        )?;
+
+        Ok(reply.get::<(u32,)>().map(|(id,)| id).unwrap_or(0))
+    }
+}
+
+/// Arguments for `Notify`, signature `(susssasa{sv}i)`.
+fn notify_params(event: &AlertEvent) -> glib::Variant {
+    let icon = Config::find_icon(&event.icon)
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut hints: HashMap<String, glib::Variant> = HashMap::new();
+    hints.insert("desktop-entry".into(), APP_NAME.to_variant());
+    // Normal urgency
+    hints.insert("urgency".into(), 1u8.to_variant());
+
+    (
+        APP_NAME,
+        0u32,
+        icon,
+        event.summary.as_str(),
+        event.body.as_str(),
+        Vec::<String>::new(),
+        hints,
+        EXPIRE_TIMEOUT,
+    ).to_variant()
+}
+
+/// Send a sample notification for `--test-notification` and report the result.
+pub fn send_test() -> bool {
+    let event = AlertEvent {
+        symbol: "BTC/USD".into(),
+        summary: "BTC above $70000".into(),
+        body: "Test notification from waybar-crypto-ticker".into(),
+        icon: "btc.svg".into(),
+    };
+
+    match Notifier::new().send_sync(&event) {
+        Ok(id) => {
+            println!("Sent test notification (id {})", id);
+            true
+        }
+        Err(e) => {
+            eprintln!("Failed to send test notification: {}", e);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::time::{Duration, Instant};
+
+    const SERVER_XML: &str = r#"
+        <node>
+          <interface name="org.freedesktop.Notifications">
+            <method name="Notify">
+              <arg type="s" direction="in"/>
+              <arg type="u" direction="in"/>
+              <arg type="s" direction="in"/>
+              <arg type="s" direction="in"/>
+              <arg type="s" direction="in"/>
+              <arg type="as" direction="in"/>
+              <arg type="a{sv}" direction="in"/>
+              <arg type="i" direction="in"/>
+              <arg type="u" direction="out"/>
+            </method>
+          </interface>
+        </node>
+    "#;
+
+    type NotifyArgs = (String, u32, String, String, String, Vec<String>, HashMap<String, glib::Variant>, i32);
+
+    /// Own `org.freedesktop.Notifications` on the session bus with a server
+    /// that records each `Notify` call.
+    fn fake_server(received: Rc<RefCell<Vec<NotifyArgs>>>) -> gio::DBusConnection {
+        let address = std::env::var("DBUS_SESSION_BUS_ADDRESS").expect("DBUS_SESSION_BUS_ADDRESS is set");
+        let server = gio::DBusConnection::for_address_sync(
+            &address,
+            gio::DBusConnectionFlags::AUTHENTICATION_CLIENT | gio::DBusConnectionFlags::MESSAGE_BUS_CONNECTION,
+            None::<&gio::DBusAuthObserver>,
+            gio::Cancellable::NONE,
+        ).unwrap();
+
+        let node = gio::DBusNodeInfo::for_xml(SERVER_XML).unwrap();
+        let interface = node.lookup_interface(INTERFACE).unwrap();
+        server.register_object(OBJECT_PATH, &interface)
+            .method_call(move |_, _, _, _, _, params, invocation| {
+                received.borrow_mut().push(params.get().expect("Notify arguments"));
+                invocation.return_value(Some(&(7u32,).to_variant()));
+            })
+            .build()
+            .unwrap();
+
+        // DBUS_NAME_FLAG_DO_NOT_QUEUE; 1 = primary owner
+        let reply = server.call_sync(
+            Some("org.freedesktop.DBus"),
+            "/org/freedesktop/DBus",
+            "org.freedesktop.DBus",
+            "RequestName",
+            Some(&(BUS_NAME, 4u32).to_variant()),
+            Some(glib::VariantTy::new("(u)").unwrap()),
+            gio::DBusCallFlags::NONE,
+            -1,
+            gio::Cancellable::NONE,
+        ).unwrap();
+        assert_eq!(reply.get::<(u32,)>(), Some((1,)), "another notification server owns the name");
+
+        server
+    }
+
+    #[test]
+    #[ignore = "needs a private session bus: dbus-run-session -- cargo test -- --ignored"]
+    fn send_calls_notify_on_the_session_bus() {
+        let context = glib::MainContext::new();
+        context.with_thread_default(|| {
+            let received = Rc::new(RefCell::new(Vec::new()));
+            let _server = fake_server(received.clone());
+
+            let event = AlertEvent {
+                symbol: "BTC/USD".into(),
+                summary: "BTC above $70,000".into(),
+                body: "Now $70,120".into(),
+                icon: "no-such-icon.svg".into(),
+            };
+            Notifier::new().send(&event);
+
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while received.borrow().is_empty() && Instant::now() < deadline {
+                if !context.iteration(false) {
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+            }
+
+            let calls = received.borrow();
+            assert_eq!(calls.len(), 1);
+            let (app, replaces, icon, summary, body, actions, hints, timeout) = &calls[0];
+            assert_eq!((app.as_str(), *replaces, icon.as_str()), (APP_NAME, 0, ""));
+            assert_eq!((summary.as_str(), body.as_str()), ("BTC above $70,000", "Now $70,120"));
+            assert!(actions.is_empty());
+            assert_eq!(hints.get("desktop-entry").and_then(|v| v.str()), Some(APP_NAME));
+            assert_eq!(*timeout, EXPIRE_TIMEOUT);
+        }).unwrap();
+    }
+}
@@ -0,0 +1,262 @@
+//! Number formatting: the locale's decimal mark and digit grouping,
+//! precision, and compact notation (`67.1k`, `1.2M`).
+
+/// Languages that write `1.234,5`.
+const COMMA_DOT: [&str; 14] = ["de", "es", "it", "nl", "pt", "da", "id", "tr", "el", "ro", "hr", "sl", "is", "vi"];
+
+/// Languages that write `1 234,5`, grouping with a narrow no-break space.
+const COMMA_SPACE: [&str; 16] = ["fr", "ru", "pl", "cs", "sk", "sv", "fi", "nb", "nn", "no", "uk", "hu", "bg", "lt", "lv", "et"];
+
+/// Languages that put the currency symbol after the amount (`5 €`).
+const SYMBOL_AFTER: [&str; 22] = [
+    "de", "fr", "es", "it", "pl", "cs", "sk", "sv", "fi", "da", "nb", "nn",
+    "no", "ru", "uk", "hu", "ro", "el", "bg", "hr", "sl", "lt",
+];
+
+/// Most decimals or significant figures a number can be given.
+pub const MAX_DECIMALS: usize = 10;
+
+/// Suffixes for compact notation, largest first.
+const COMPACT_SUFFIXES: [(f64, &str); 4] = [(1e12, "T"), (1e9, "B"), (1e6, "M"), (1e3, "k")];
+
+/// How many digits a number keeps.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Precision {
+    /// Fewer decimals the larger the number: none from 1000, 2 from 1, 4
+    /// from 0.01 and 6 below that.
+    Auto,
+    Decimals(usize),
+    Significant(usize),
+}
+
+/// How numbers are written, resolved from `[numbers]` and the locale.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NumberFormat {
+    pub decimal: char,
+    /// Separator between groups of three integer digits; `None` leaves
+    /// them ungrouped.
+    pub group: Option<String>,
+    /// Whether currency symbols go before the amount (`$5`) or after (`5 €`).
+    pub symbol_first: bool,
+    /// Shorten amounts from 1000 up to three significant figures and a
+    /// suffix.
+    pub compact: bool,
+    /// Decimals in percentages.
+    pub percent_decimals: usize,
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        Self {
+            decimal: '.',
+            group: Some(",".to_string()),
+            symbol_first: true,
+            compact: false,
+            percent_decimals: 1,
+        }
+    }
+}
+
+impl NumberFormat {
+    /// Marks and symbol placement for a locale name like `de_DE.UTF-8`;
+    /// `None` reads `LC_ALL`, `LC_MONETARY`, `LC_NUMERIC` and `LANG`.
+    pub fn for_locale(locale: Option<&str>) -> Self {
+        let locale = locale.map(str::to_string).unwrap_or_else(|| {
+            ["LC_ALL", "LC_MONETARY", "LC_NUMERIC", "LANG"].iter()
+                .filter_map(|var| std::env::var(var).ok())
+                .find(|value| !value.is_empty())
+                .unwrap_or_default()
+        });
+        let mut parts = locale.split(['_', '-', '.', '@']);
+        let language = parts.next().unwrap_or("").to_lowercase();
+        let territory = parts.next().unwrap_or("").to_uppercase();
+
+        let (decimal, group) = if territory == "CH" {
+            ('.', "’")
+        } else if COMMA_DOT.contains(&language.as_str()) {
+            (',', ".")
+        } else if COMMA_SPACE.contains(&language.as_str()) {
+            (',', "\u{202f}")
+        } else {
+            ('.', ",")
+        };
+
+        Self {
+            decimal,
+            group: Some(group.to_string()),
+            symbol_first: !SYMBOL_AFTER.contains(&language.as_str()),
+            ..Self::default()
+        }
+    }
+
+    /// A number at `precision`, or compact if enabled and it's large.
+    pub fn number(&self, value: f64, precision: Precision) -> String {
+        let magnitude = value.abs();
+        if self.compact && magnitude >= 1000.0 {
+            return self.compact_number(value);
+        }
+
+        let decimals = match precision {
+            Precision::Auto if magnitude >= 1000.0 => 0,
+            Precision::Auto if magnitude >= 1.0 => 2,
+            Precision::Auto if magnitude >= 0.01 => 4,
+            Precision::Auto => 6,
+            Precision::Decimals(decimals) => decimals,
+            Precision::Significant(digits) if magnitude > 0.0 => {
+                let integer_digits = magnitude.log10().floor() as i64 + 1;
+                (digits as i64 - integer_digits).max(0) as usize
+            }
+            Precision::Significant(digits) => digits.saturating_sub(1),
+        };
+        self.localize(&format!("{:.*}", decimals, value))
+    }
+
+    /// A number with every digit it has, e.g. an amount held.
+    pub fn exact(&self, value: f64) -> String {
+        self.localize(&value.to_string())
+    }
+
+    /// A signed percentage like `+1.2%`.
+    pub fn percent(&self, value: f64) -> String {
+        format!("{}%", self.localize(&format!("{:+.*}", self.percent_decimals, value)))
+    }
+
+    /// A percentage with no `+` for positive values, like `0.0%`.
+    pub fn percent_plain(&self, value: f64) -> String {
+        format!("{}%", self.localize(&format!("{:.*}", self.percent_decimals, value)))
+    }
+
+    /// Three significant figures and a suffix: `1.23k`, `67.1k`, `123M`.
+    fn compact_number(&self, value: f64) -> String {
+        let magnitude = value.abs();
+        let mut index = COMPACT_SUFFIXES.iter().position(|(scale, _)| magnitude >= *scale);
+        let (mut scaled, mut decimals) = three_figures(magnitude / index.map_or(1.0, |i| COMPACT_SUFFIXES[i].0));
+
+        // Rounding can carry into the next suffix: 999,950 is `1.00M`, not `1000k`
+        if let Some(i) = index.filter(|i| *i > 0 && scaled >= 1000.0) {
+            (scaled, decimals) = three_figures(magnitude / COMPACT_SUFFIXES[i - 1].0);
+            index = Some(i - 1);
+        }
+
+        let suffix = index.map_or("", |i| COMPACT_SUFFIXES[i].1);
+        format!("{}{}", self.localize(&format!("{:.*}", decimals, scaled.copysign(value))), suffix)
+    }
+
+    /// Swap in the locale's decimal mark and group the integer digits of
+    /// a number formatted by Rust (`-1234.5` or `+0.25`).
+    fn localize(&self, formatted: &str) -> String {
+        let (sign, digits) = match formatted.strip_prefix(['-', '+']) {
+            Some(rest) => formatted.split_at(formatted.len() - rest.len()),
+            None => ("", formatted),
+        };
+        let (integer, fraction) = match digits.split_once('.') {
+            Some((integer, fraction)) => (integer, Some(fraction)),
+            None => (digits, None),
+        };
+
+        let mut out = sign.to_string();
+        for (i, digit) in integer.chars().enumerate() {
+            if i > 0 && (integer.len() - i) % 3 == 0 {
+                if let Some(group) = &self.group {
+                    out.push_str(group);
+                }
+            }
+            out.push(digit);
+        }
+        if let Some(fraction) = fraction {
+            out.push(self.decimal);
+            out.push_str(fraction);
+        }
+        out
+    }
+}
+
+/// A magnitude rounded to three significant figures, and the decimals that
+/// leaves.
+fn three_figures(magnitude: f64) -> (f64, usize) {
+    let decimals = |m: f64| match m {
+        m if m >= 100.0 => 0,
+        m if m >= 10.0 => 1,
+        _ => 2,
+    };
+    let factor = 10f64.powi(decimals(magnitude) as i32);
+    let rounded = (magnitude * factor).round() / factor;
+    // Rounding up can add a digit: 99.96 is `100`, not `100.0`
+    (rounded, decimals(rounded))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compact() -> NumberFormat {
+        NumberFormat { compact: true, ..NumberFormat::default() }
+    }
+
+    #[test]
+    fn groups_digits_for_the_locale() {
+        let value = 1234567.5;
+        let precision = Precision::Decimals(1);
+        assert_eq!(NumberFormat::for_locale(Some("en_US.UTF-8")).number(value, precision), "1,234,567.5");
+        assert_eq!(NumberFormat::for_locale(Some("de_DE")).number(value, precision), "1.234.567,5");
+        assert_eq!(NumberFormat::for_locale(Some("fr_FR")).number(value, precision), "1\u{202f}234\u{202f}567,5");
+        assert_eq!(NumberFormat::for_locale(Some("de_CH")).number(value, precision), "1’234’567.5");
+    }
+
+    #[test]
+    fn ungrouped_without_separator() {
+        let numbers = NumberFormat { group: None, ..NumberFormat::default() };
+        assert_eq!(numbers.number(1234567.0, Precision::Decimals(0)), "1234567");
+    }
+
+    #[test]
+    fn sign_stays_outside_grouping() {
+        let numbers = NumberFormat::default();
+        assert_eq!(numbers.number(-1234.5, Precision::Decimals(1)), "-1,234.5");
+        assert_eq!(numbers.number(-123.0, Precision::Decimals(0)), "-123");
+        assert_eq!(numbers.percent(1234.56), "+1,234.6%");
+        assert_eq!(numbers.percent(-0.25), "-0.2%");
+        assert_eq!(numbers.percent_plain(0.0), "0.0%");
+    }
+
+    #[test]
+    fn auto_precision_shrinks_with_size() {
+        let numbers = NumberFormat::default();
+        assert_eq!(numbers.number(67123.4, Precision::Auto), "67,123");
+        assert_eq!(numbers.number(1.5, Precision::Auto), "1.50");
+        assert_eq!(numbers.number(0.45, Precision::Auto), "0.4500");
+        assert_eq!(numbers.number(0.0025, Precision::Auto), "0.002500");
+    }
+
+    #[test]
+    fn decimals_and_significant_figures() {
+        let numbers = NumberFormat::default();
+        assert_eq!(numbers.number(1234.5678, Precision::Decimals(3)), "1,234.568");
+        assert_eq!(numbers.number(1234.5678, Precision::Significant(3)), "1,235");
+        assert_eq!(numbers.number(1.234567, Precision::Significant(3)), "1.23");
+        assert_eq!(numbers.number(0.012345, Precision::Significant(3)), "0.0123");
+        assert_eq!(numbers.number(0.0, Precision::Significant(3)), "0.00");
+    }
+
+    #[test]
+    fn compact_keeps_three_figures() {
+        let numbers = compact();
+        assert_eq!(numbers.number(999.0, Precision::Auto), "999.00");
+        assert_eq!(numbers.number(1234.0, Precision::Auto), "1.23k");
+        assert_eq!(numbers.number(67123.0, Precision::Auto), "67.1k");
+        assert_eq!(numbers.number(123_456_789.0, Precision::Auto), "123M");
+        assert_eq!(numbers.number(-1_500_000.0, Precision::Auto), "-1.50M");
+    }
+
+    #[test]
+    fn compact_rounding_carries_to_the_next_suffix() {
+        let numbers = compact();
+        assert_eq!(numbers.number(999_499.0, Precision::Auto), "999k");
+        assert_eq!(numbers.number(999_950.0, Precision::Auto), "1.00M");
+        assert_eq!(numbers.number(-999_950.0, Precision::Auto), "-1.00M");
+        assert_eq!(numbers.number(999_999_999.0, Precision::Auto), "1.00B");
+        assert_eq!(numbers.number(99_960.0, Precision::Auto), "100k");
+        assert_eq!(numbers.number(9_999.0, Precision::Auto), "10.0k");
+        assert_eq!(numbers.number(999.96e12, Precision::Auto), "1,000T");
+    }
+}
@@ -0,0 +1,501 @@
+//! The ticker strip: every segment drawn side by side into an offscreen
+//! surface. It's re-rendered only when the segments change, so a frame is
+//! a blit of the whole strip at the scroll offset, or of single segments
+//! for the other animation modes. Tick flashes fade and alert highlights
+//! blink on every frame, so they're drawn at blit time rather than into
+//! the strip.
+
+use crate::config::{Appearance, TickFlash};
+use crate::ticker::{self, Direction, Segment};
+use gtk4::{cairo, pango};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+/// Horizontal space between a segment's icon and its text.
+const ICON_GAP: f64 = 4.0;
+
+/// Horizontal space between a segment's text and its sparkline.
+const SPARKLINE_GAP: f64 = 6.0;
+
+/// Vertical padding above and below a sparkline.
+const SPARKLINE_PADDING: f64 = 4.0;
+
+/// Half-period of the alert highlight blink, in milliseconds.
+const ALERT_BLINK_MS: u128 = 300;
+
+/// Widest image Cairo can create, in device pixels.
+const MAX_TILE_PX: i32 = 32767;
+
+/// Opacity of a tick flash's background as it starts.
+const TICK_FLASH_ALPHA: f64 = 0.35;
+
+/// What a strip was rendered from; it's out of date once this changes.
+#[derive(PartialEq)]
+pub struct StripKey {
+    /// `TickerState::revision` of the segments.
+    revision: u64,
+    height: i32,
+    /// Device pixels per logical pixel; fractional on scaled outputs.
+    scale: f64,
+}
+
+impl StripKey {
+    pub fn new(revision: u64, height: i32, scale: f64) -> Self {
+        Self {
+            revision,
+            height,
+            scale: scale.max(1.0),
+        }
+    }
+}
+
+/// A segment's last price move, flashed over the strip as it fades.
+struct Flash {
+    at: Instant,
+    color: (f64, f64, f64),
+    /// Where the segment's text is, from the segment's start.
+    text: Range<f64>,
+}
+
+/// A segment's alert highlight, blinking until `until`.
+struct Highlight {
+    until: Instant,
+    color: (f64, f64, f64),
+}
+
+/// Window x range and symbol of each segment as last drawn, for
+/// hit-testing.
+pub type HitBoxes = Vec<(Range<f64>, Option<String>)>;
+
+/// Rendered segments, ready to blit.
+pub struct Strip {
+    /// The rendered strip, in tiles no wider than `MAX_TILE_PX`, each with
+    /// the x it starts at.
+    tiles: Vec<(f64, cairo::ImageSurface)>,
+    /// Width of each segment, in order.
+    pub widths: Vec<f64>,
+    pub width: f64,
+    /// Where each segment starts in the strip.
+    offsets: Vec<f64>,
+    /// Symbol of each segment; `None` for separators.
+    symbols: Vec<Option<String>>,
+    /// Where each segment's text is, from the segment's start.
+    texts: Vec<Range<f64>>,
+    /// Tick flash of each segment, if it has had a price move.
+    flashes: Vec<Option<Flash>>,
+    /// Alert highlight of each segment, if an alert fired recently.
+    highlights: Vec<Option<Highlight>>,
+    flash_style: TickFlash,
+    flash_duration: Duration,
+    key: StripKey,
+}
+
+impl Strip {
+    /// Render `segments` from x = 0 into a strip `key.height` logical pixels
+    /// tall, at `key.scale` device pixels per logical pixel. `None` if
+    /// there's nothing to draw or the surface can't be created.
+    pub fn render(
+        segments: &[Segment],
+        key: StripKey,
+        appearance: &Appearance,
+        icons: &HashMap<String, cairo::ImageSurface>,
+    ) -> Option<Self> {
+        let height = key.height as f64;
+        let font = pango::FontDescription::from_string(&appearance.font);
+        let icon_space = appearance.icon_size as f64 + ICON_GAP;
+
+        // Lay out and measure on a scratch surface, before the size is known
+        let scale = key.scale;
+        let scratch = cairo::ImageSurface::create(cairo::Format::ARgb32, 1, 1).ok()?;
+        scratch.set_device_scale(scale, scale);
+        let measure = cairo::Context::new(&scratch).ok()?;
+        let layouts: Vec<pango::Layout> = segments.iter()
+            .map(|seg| text_layout(&measure, &font, &seg.markup))
+            .collect();
+
+        let mut widths = Vec::with_capacity(segments.len());
+        let mut text_widths = Vec::with_capacity(segments.len());
+        for (seg, layout) in segments.iter().zip(&layouts) {
+            let text_w = pango_units(layout.size().0);
+            let mut w = text_w;
+            if seg.icon.is_some() {
+                w += icon_space;
+            }
+            if let Some(ref spark) = seg.sparkline {
+                w += SPARKLINE_GAP + spark.width;
+            }
+            text_widths.push(text_w);
+            widths.push(w);
+        }
+        let width: f64 = widths.iter().sum();
+        if width <= 0.0 {
+            return None;
+        }
+
+        let offsets: Vec<f64> = widths.iter()
+            .scan(0.0, |x, w| {
+                let start = *x;
+                *x += w;
+                Some(start)
+            })
+            .collect();
+        let text_xs: Vec<f64> = segments.iter().zip(&offsets)
+            .map(|(seg, x)| if seg.icon.is_some() { x + icon_space } else { *x })
+            .collect();
+        let baseline_y = baseline(&measure, &font, height);
+        let icon_y = (height - appearance.icon_size as f64) / 2.0;
+
+        // Draw the segments that reach into `visible`
+        let draw = |cr: &cairo::Context, visible: Range<f64>| {
+            for (i, seg) in segments.iter().enumerate() {
+                let x = offsets[i];
+                if x + widths[i] + 2.0 < visible.start || x - 2.0 > visible.end {
+                    continue;
+                }
+                let color = color_for(seg.direction, appearance);
+
+                if let Some(ref icon_name) = seg.icon {
+                    if let Some(icon) = icons.get(icon_name) {
+                        let _ = cr.set_source_surface(icon, x, icon_y);
+                        let _ = cr.paint();
+                    }
+                }
+
+                pangocairo::functions::update_layout(cr, &layouts[i]);
+                cr.set_source_rgb(color.0, color.1, color.2);
+                cr.move_to(text_xs[i], baseline_y - pango_units(layouts[i].baseline()));
+                pangocairo::functions::show_layout(cr, &layouts[i]);
+
+                if let Some(ref spark) = seg.sparkline {
+                    draw_sparkline(cr, spark, text_xs[i] + text_widths[i] + SPARKLINE_GAP, height, color);
+                }
+            }
+        };
+
+        // Cairo images are limited in width, so a long strip is split into tiles
+        let device_width = (width * scale).ceil() as i32;
+        let device_height = (height * scale).ceil() as i32;
+        let mut tiles = Vec::new();
+        for start in (0..device_width).step_by(MAX_TILE_PX as usize) {
+            let tile_width = (device_width - start).min(MAX_TILE_PX);
+            let surface = match cairo::ImageSurface::create(
+                cairo::Format::ARgb32,
+                tile_width,
+                device_height,
+            ) {
+                Ok(surface) => surface,
+                Err(e) => {
+                    eprintln!(
+                        "Warning: Can't create a {}x{} px ticker strip: {}",
+                        tile_width, device_height, e,
+                    );
+                    return None;
+                }
+            };
+            surface.set_device_scale(scale, scale);
+            let cr = cairo::Context::new(&surface).ok()?;
+
+            let x = start as f64 / scale;
+            cr.translate(-x, 0.0);
+            draw(&cr, x..x + tile_width as f64 / scale);
+            drop(cr);
+            tiles.push((x, surface));
+        }
+
+        let texts = text_xs.iter().zip(&offsets).zip(&text_widths)
+            .map(|((text_x, x), w)| text_x - x..text_x - x + w)
+            .collect();
+        let symbols = segments.iter().map(|seg| seg.symbol.clone()).collect();
+        let mut strip = Self {
+            tiles,
+            widths,
+            width,
+            offsets,
+            symbols,
+            texts,
+            flashes: Vec::new(),
+            highlights: Vec::new(),
+            flash_style: appearance.tick_flash,
+            flash_duration: appearance.tick_flash_duration,
+            key,
+        };
+        strip.show_flashes(segments, appearance);
+        Some(strip)
+    }
+
+    /// Take the tick flashes and alert highlights from `segments`, which
+    /// must be what the strip was rendered from, with only those changed.
+    pub fn show_flashes(&mut self, segments: &[Segment], appearance: &Appearance) {
+        self.flashes = segments.iter().zip(&self.texts)
+            .map(|(seg, text)| {
+                let tick = seg.tick?;
+                let flash = color_for(tick.direction, appearance);
+                Some(Flash {
+                    at: tick.at,
+                    // Lightened for text, so it shows even on text already in that colour
+                    color: match appearance.tick_flash {
+                        TickFlash::Text => mix(flash, (1.0, 1.0, 1.0), 0.5),
+                        _ => flash,
+                    },
+                    text: text.clone(),
+                })
+            })
+            .collect();
+        self.highlights = segments.iter()
+            .map(|seg| Some(Highlight {
+                until: seg.flash_until?,
+                color: color_for(seg.direction, appearance),
+            }))
+            .collect();
+    }
+
+    /// Whether this strip shows what `key` describes.
+    pub fn is_current(&self, key: &StripKey) -> bool {
+        self.key == *key
+    }
+
+    /// Draw the strip scrolled left by `offset`, repeating it to fill
+    /// `width`.
+    pub fn draw_scroll(&self, cr: &cairo::Context, offset: f64, width: f64) -> HitBoxes {
+        let mut hits = Vec::new();
+        let mut x = -offset.rem_euclid(self.width);
+        while x < width {
+            self.paint(cr, x, 0.0, 1.0);
+            hits.extend(self.symbols.iter().enumerate().map(|(i, symbol)| {
+                let start = x + self.offsets[i];
+                (start..start + self.widths[i], symbol.clone())
+            }));
+            x += self.width;
+        }
+        hits
+    }
+
+    /// Draw every coin side by side, each in an equal share of `width`.
+    /// Coins wider than their slot are cut off.
+    pub fn draw_static(&self, cr: &cairo::Context, width: f64) -> HitBoxes {
+        let coins = self.coins();
+        if coins.is_empty() {
+            return Vec::new();
+        }
+        let slot = width / coins.len() as f64;
+        coins.into_iter().enumerate()
+            .map(|(n, i)| {
+                let x = n as f64 * slot;
+                self.draw_segment(cr, i, x, 0.0, slot, 1.0);
+                (x..x + slot, self.symbols[i].clone())
+            })
+            .collect()
+    }
+
+    /// Draw one coin at a time, centred, moving to the next every
+    /// `page` of `clock`. The change takes `transition`: a cross-fade, or
+    /// with `roll` the old coin moving up out of view as the next rises in.
+    pub fn draw_paged(
+        &self,
+        cr: &cairo::Context,
+        clock: Duration,
+        page: Duration,
+        transition: Duration,
+        roll: bool,
+        width: f64,
+    ) -> HitBoxes {
+        let coins = self.coins();
+        if coins.is_empty() {
+            return Vec::new();
+        }
+        let page_secs = page.as_secs_f64();
+        let number = (clock.as_secs_f64() / page_secs).floor();
+        let into_page = clock.as_secs_f64() - number * page_secs;
+        let current = coins[number as usize % coins.len()];
+        let x_of = |i: usize| ((width - self.widths[i]) / 2.0).max(0.0);
+
+        // Part way through the change from the previous coin, eased
+        let transition = transition.as_secs_f64().min(page_secs);
+        let progress = if number >= 1.0 && into_page < transition {
+            let t = into_page / transition;
+            Some(t * t * (3.0 - 2.0 * t))
+        } else {
+            None
+        };
+
+        match progress {
+            Some(t) => {
+                let previous = coins[(number as usize - 1) % coins.len()];
+                let height = self.key.height as f64;
+                if roll {
+                    self.draw_segment(cr, previous, x_of(previous), -t * height, width, 1.0);
+                    self.draw_segment(cr, current, x_of(current), (1.0 - t) * height, width, 1.0);
+                } else {
+                    self.draw_segment(cr, previous, x_of(previous), 0.0, width, 1.0 - t);
+                    self.draw_segment(cr, current, x_of(current), 0.0, width, t);
+                }
+            }
+            None => self.draw_segment(cr, current, x_of(current), 0.0, width, 1.0),
+        }
+        vec![(0.0..width, self.symbols[current].clone())]
+    }
+
+    /// Indices of the coin and total segments, skipping separators.
+    fn coins(&self) -> Vec<usize> {
+        (0..self.symbols.len()).filter(|&i| self.symbols[i].is_some()).collect()
+    }
+
+    /// Draw segment `i` with its left edge at (`x`, `y`), no wider than
+    /// `max_width`.
+    fn draw_segment(&self, cr: &cairo::Context, i: usize, x: f64, y: f64, max_width: f64, alpha: f64) {
+        let y = self.snap(y);
+        let _ = cr.save();
+        cr.rectangle(self.snap(x), y, self.widths[i].min(max_width), self.key.height as f64);
+        cr.clip();
+        self.paint(cr, x - self.offsets[i], y, alpha);
+        let _ = cr.restore();
+    }
+
+    /// Paint the strip with its left edge at (`x`, `y`) and `alpha`
+    /// opacity, with the tick flashes and alert highlights as they are now.
+    fn paint(&self, cr: &cairo::Context, x: f64, y: f64, alpha: f64) {
+        let (x, y) = (self.snap(x), self.snap(y));
+        let height = self.key.height as f64;
+
+        // Highlight behind segments with a fresh alert, in its blink phase
+        for (i, highlight) in self.highlights.iter().enumerate() {
+            if let Some(Highlight { until, color: (r, g, b) }) = highlight {
+                if highlight_on(*until) {
+                    cr.set_source_rgba(*r, *g, *b, 0.3 * alpha);
+                    cr.rectangle(x + self.offsets[i] - 2.0, y + 2.0, self.widths[i] + 4.0, height - 4.0);
+                    let _ = cr.fill();
+                }
+            }
+        }
+        let flashes: Vec<(usize, &Flash, f64)> = self.flashes.iter().enumerate()
+            .filter_map(|(i, flash)| {
+                let flash = flash.as_ref()?;
+                let strength = self.flash_strength(flash);
+                (strength > 0.0).then_some((i, flash, strength))
+            })
+            .collect();
+
+        // Background flashes go behind the segment
+        if self.flash_style == TickFlash::Background {
+            for (i, flash, strength) in &flashes {
+                let (r, g, b) = flash.color;
+                cr.set_source_rgba(r, g, b, TICK_FLASH_ALPHA * strength * alpha);
+                cr.rectangle(x + self.offsets[*i] - 2.0, y, self.widths[*i] + 4.0, height);
+                let _ = cr.fill();
+            }
+        }
+
+        for (start, tile) in &self.tiles {
+            let _ = cr.set_source_surface(tile, x + start, y);
+            let _ = cr.paint_with_alpha(alpha);
+        }
+
+        // Text flashes tint the glyphs, masked by the strip itself
+        if self.flash_style == TickFlash::Text {
+            for (i, flash, strength) in &flashes {
+                let start = x + self.offsets[*i] + flash.text.start;
+                let _ = cr.save();
+                cr.rectangle(start, y, flash.text.end - flash.text.start, height);
+                cr.clip();
+                let (r, g, b) = flash.color;
+                cr.set_source_rgba(r, g, b, strength * alpha);
+                for (start, tile) in &self.tiles {
+                    let _ = cr.mask_surface(tile, x + start, y);
+                }
+                let _ = cr.restore();
+            }
+        }
+    }
+
+    /// How much of a tick flash is left to fade, from 1.0 down to 0.0.
+    fn flash_strength(&self, flash: &Flash) -> f64 {
+        let duration = self.flash_duration.as_secs_f64();
+        if self.flash_style == TickFlash::None || duration <= 0.0 {
+            return 0.0;
+        }
+        (1.0 - flash.at.elapsed().as_secs_f64() / duration).max(0.0)
+    }
+
+    /// Round to whole device pixels, so text stays sharp.
+    fn snap(&self, value: f64) -> f64 {
+        (value * self.key.scale).round() / self.key.scale
+    }
+}
+
+/// Whether an alert highlight lasting until `until` is showing right now.
+fn highlight_on(until: Instant) -> bool {
+    let remaining = until.saturating_duration_since(Instant::now());
+    !remaining.is_zero() && (remaining.as_millis() / ALERT_BLINK_MS).is_multiple_of(2)
+}
+
+/// `from` moved `amount` (0.0 ..= 1.0) of the way to `to`.
+fn mix(from: (f64, f64, f64), to: (f64, f64, f64), amount: f64) -> (f64, f64, f64) {
+    (
+        from.0 + (to.0 - from.0) * amount,
+        from.1 + (to.1 - from.1) * amount,
+        from.2 + (to.2 - from.2) * amount,
+    )
+}
+
+fn color_for(direction: Direction, appearance: &Appearance) -> (f64, f64, f64) {
+    match direction {
+        Direction::Up => appearance.color_up,
+        Direction::Down => appearance.color_down,
+        Direction::Neutral => appearance.color_neutral,
+        Direction::Stale => appearance.color_stale,
+    }
+}
+
+/// Y of the text baseline that centres `font` vertically in `height`.
+pub fn baseline(cr: &cairo::Context, font: &pango::FontDescription, height: f64) -> f64 {
+    let metrics = pangocairo::functions::create_layout(cr).context().metrics(Some(font), None);
+    let (ascent, descent) = (pango_units(metrics.ascent()), pango_units(metrics.descent()));
+    (height - ascent - descent) / 2.0 + ascent
+}
+
+/// A Pango layout of `markup` in `font`, for measuring and drawing on `cr`.
+pub fn text_layout(cr: &cairo::Context, font: &pango::FontDescription, markup: &str) -> pango::Layout {
+    let layout = pangocairo::functions::create_layout(cr);
+    layout.set_font_description(Some(font));
+    layout.set_markup(markup);
+    layout
+}
+
+/// Pango units to pixels.
+pub fn pango_units(value: i32) -> f64 {
+    value as f64 / pango::SCALE as f64
+}
+
+/// Draw a sparkline as a polyline starting at `x`, spanning the bar height.
+fn draw_sparkline(
+    cr: &cairo::Context,
+    spark: &ticker::Sparkline,
+    x: f64,
+    height: f64,
+    fallback: (f64, f64, f64),
+) {
+    if spark.points.len() < 2 {
+        return;
+    }
+
+    let top = SPARKLINE_PADDING;
+    let span = (height - 2.0 * SPARKLINE_PADDING).max(1.0);
+    let step = spark.width / (spark.points.len() - 1) as f64;
+
+    let (r, g, b) = spark.color.unwrap_or(fallback);
+    cr.set_source_rgb(r, g, b);
+    cr.set_line_width(1.2);
+    cr.set_line_join(cairo::LineJoin::Round);
+
+    for (i, p) in spark.points.iter().enumerate() {
+        let px = x + i as f64 * step;
+        let py = top + (1.0 - p) * span;
+        if i == 0 {
+            cr.move_to(px, py);
+        } else {
+            cr.line_to(px, py);
+        }
+    }
+    let _ = cr.stroke();
+}
@@ -1,7 +1,11 @@
 //! Ticker state and display segment management.
 
-use crate::config::{CoinConfig, Config};
-use std::collections::HashMap;
+use crate::alerts::{Alert, AlertEvent};
+use crate::config::{CoinConfig, Config, Portfolio};
+use crate::currency;
+use crate::number::{NumberFormat, Precision};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant, SystemTime};
 
 /// Price movement direction for coloring.
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -9,14 +13,148 @@ pub enum Direction {
     Up,
     Down,
     Neutral,
+    /// No update for longer than `appearance.stale_after`.
+    Stale,
+}
+
+impl Direction {
+    /// CSS class name, as used by `--waybar` output.
+    pub fn class(self) -> &'static str {
+        match self {
+            Direction::Up => "up",
+            Direction::Down => "down",
+            Direction::Neutral => "neutral",
+            Direction::Stale => "stale",
+        }
+    }
+}
+
+/// Health of the exchange connections feeding the ticker.
+///
+/// Variants are ordered from healthy to unhealthy, so the overall state is
+/// the maximum across sources.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ConnectionState {
+    /// Receiving ticks.
+    Live,
+    /// Opening the WebSocket and subscribing.
+    Connecting,
+    /// Connected, but no ticks for a while. A reconnect follows only if
+    /// nothing at all (not even a heartbeat) arrives.
+    Stale,
+    /// Waiting to retry after a failure.
+    BackingOff,
 }
 
 /// A rendered segment of the ticker display.
 #[derive(Clone)]
 pub struct Segment {
+    /// Config symbol of the coin shown; `None` for separators.
+    pub symbol: Option<String>,
     pub text: String,
+    /// `text` as Pango markup, with `appearance.spans` applied.
+    pub markup: String,
     pub direction: Direction,
     pub icon: Option<String>,
+    pub sparkline: Option<Sparkline>,
+    /// Highlight the segment until this time because an alert fired.
+    pub flash_until: Option<Instant>,
+    /// 24h change in percent, when the open is known.
+    pub change_pct: Option<f64>,
+    /// The last tick that moved the price, for the tick flash.
+    pub tick: Option<Tick>,
+}
+
+impl Segment {
+    /// Whether `other` draws the same, tick and alert flashes aside.
+    fn looks_like(&self, other: &Segment) -> bool {
+        self.symbol == other.symbol
+            && self.text == other.text
+            && self.markup == other.markup
+            && self.direction == other.direction
+            && self.icon == other.icon
+            && self.sparkline == other.sparkline
+    }
+
+    /// Whether this is the portfolio total rather than a coin or separator.
+    pub fn is_total(&self) -> bool {
+        self.symbol.as_deref() == Some(TOTAL_SYMBOL)
+    }
+
+    /// The gap between two coins.
+    fn separator() -> Self {
+        Segment {
+            symbol: None,
+            text: SEPARATOR.to_string(),
+            markup: SEPARATOR.to_string(),
+            direction: Direction::Neutral,
+            icon: None,
+            sparkline: None,
+            flash_until: None,
+            change_pct: None,
+            tick: None,
+        }
+    }
+}
+
+/// The segments of only the coins in `symbols` (in config order) and the
+/// portfolio total, with separators as `TickerState` would place them.
+pub fn select_segments(segments: &[Segment], symbols: &[String]) -> Vec<Segment> {
+    let coins: Vec<&Segment> = segments.iter()
+        .filter(|s| s.is_total() || s.symbol.as_ref().is_some_and(|symbol| symbols.contains(symbol)))
+        .collect();
+
+    let mut selected = Vec::with_capacity(coins.len() * 2);
+    for segment in &coins {
+        selected.push((*segment).clone());
+        if coins.len() > 1 {
+            selected.push(Segment::separator());
+        }
+    }
+    selected
+}
+
+/// Chart data for a segment's inline sparkline.
+#[derive(Clone, PartialEq)]
+pub struct Sparkline {
+    /// Prices scaled to 0.0 (window low) ..= 1.0 (window high), oldest first.
+    pub points: Vec<f64>,
+    pub width: f64,
+    /// Line colour; `None` follows the segment's direction colour.
+    pub color: Option<(f64, f64, f64)>,
+}
+
+/// Extra 24h market figures some exchanges send alongside the last price.
+#[derive(Clone, Copy, Default)]
+pub struct MarketStats {
+    pub bid: Option<f64>,
+    pub ask: Option<f64>,
+    pub volume: Option<f64>,
+    pub vwap: Option<f64>,
+    pub low: Option<f64>,
+    pub high: Option<f64>,
+}
+
+impl MarketStats {
+    /// Overwrite fields that `other` provides, keeping the rest.
+    fn merge(&mut self, other: &MarketStats) {
+        self.bid = other.bid.or(self.bid);
+        self.ask = other.ask.or(self.ask);
+        self.volume = other.volume.or(self.volume);
+        self.vwap = other.vwap.or(self.vwap);
+        self.low = other.low.or(self.low);
+        self.high = other.high.or(self.high);
+    }
+}
+
+/// The last update that moved a coin's price.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Tick {
+    /// The price before it.
+    pub previous: f64,
+    /// `Up` or `Down`.
+    pub direction: Direction,
+    pub at: Instant,
 }
 
 /// Price data for a single coin.
@@ -24,101 +162,931 @@ pub struct Segment {
 pub struct CoinData {
     pub price: f64,
     pub open_24h: f64,
+    pub stats: MarketStats,
+    pub updated: Instant,
+    pub last_tick: Option<Tick>,
+}
+
+impl CoinData {
+    /// The same figures converted at `rate`; volume stays in the base
+    /// currency.
+    fn converted(&self, rate: f64) -> CoinData {
+        let scale = |v: Option<f64>| v.map(|v| v * rate);
+        CoinData {
+            price: self.price * rate,
+            open_24h: self.open_24h * rate,
+            stats: MarketStats {
+                bid: scale(self.stats.bid),
+                ask: scale(self.stats.ask),
+                volume: self.stats.volume,
+                vwap: scale(self.stats.vwap),
+                low: scale(self.stats.low),
+                high: scale(self.stats.high),
+            },
+            updated: self.updated,
+            last_tick: self.last_tick.map(|tick| Tick { previous: tick.previous * rate, ..tick }),
+        }
+    }
+}
+
+/// A coin's figures in the currency it's shown in.
+struct Priced<'a> {
+    data: CoinData,
+    currency: &'a str,
+    /// The coin's `cost_basis`, if it's in `currency`.
+    cost_basis: Option<f64>,
 }
 
 /// Manages price state and generates display segments.
 pub struct TickerState {
+    /// Latest prices of the coins and of the pairs streamed for exchange
+    /// rates, by symbol.
     prices: HashMap<String, CoinData>,
     coins: Vec<CoinConfig>,
+    /// Symbols of every streamed pair, rates included.
+    feeds: Vec<String>,
+    /// Opens fetched before the first price for that symbol arrived.
+    pending_opens: HashMap<String, f64>,
+    connections: HashMap<String, ConnectionState>,
+    /// Recent prices for coins with a sparkline, oldest first.
+    history: HashMap<String, VecDeque<(Instant, f64)>>,
+    alerts: Vec<Alert>,
+    /// Fired alerts not yet collected by `take_alert_events`.
+    alert_events: Vec<AlertEvent>,
+    /// Symbols whose segment is flashing, and until when.
+    flashing: HashMap<String, Instant>,
+    stale_after: Option<Duration>,
+    portfolio: Portfolio,
+    display_currency: Option<String>,
+    numbers: NumberFormat,
+    /// Font descriptions for template placeholders, by name.
+    spans: HashMap<String, String>,
+    /// Whether the current segments include a stale coin (and so an age
+    /// that needs refreshing).
+    showing_stale: bool,
     pub segments: Vec<Segment>,
+    /// Bumped whenever `segments` is rebuilt into something that draws
+    /// differently.
+    revision: u64,
+    /// Bumped whenever a segment's tick or alert flash changes.
+    flash_revision: u64,
 }
 
 const SEPARATOR: &str = "     ·     ";
 
+/// Symbol of the portfolio total segment. Coins are always BASE/QUOTE
+/// pairs, so it can't clash with one.
+pub const TOTAL_SYMBOL: &str = "TOTAL";
+
+/// Shown in place of amounts and values when `portfolio.blur` is set.
+const BLURRED: &str = "•••••";
+
+/// How long a segment flashes after one of its alerts fires.
+const ALERT_FLASH: Duration = Duration::from_secs(6);
+
+/// Resolution of sparkline history: one point per `window / SPARKLINE_POINTS`.
+const SPARKLINE_POINTS: u32 = 120;
+
 impl TickerState {
     pub fn new(config: &Config) -> Self {
         Self {
             prices: HashMap::new(),
             coins: config.coins.clone(),
+            feeds: config.feeds().into_iter().map(|(_, symbol)| symbol).collect(),
+            pending_opens: HashMap::new(),
+            connections: HashMap::new(),
+            history: HashMap::new(),
+            alerts: config.alerts.iter().cloned().map(Alert::new).collect(),
+            alert_events: Vec::new(),
+            flashing: HashMap::new(),
+            stale_after: config.appearance.stale_after,
+            portfolio: config.portfolio.clone(),
+            display_currency: config.appearance.display_currency.clone(),
+            numbers: config.numbers.clone(),
+            spans: config.appearance.spans.clone(),
+            showing_stale: false,
             segments: Vec::new(),
+            revision: 0,
+            flash_revision: 0,
+        }
+    }
+
+    /// Switch to a new config (after a reload or a coin being added or
+    /// removed), keeping prices and history for coins that remain.
+    pub fn apply_config(&mut self, config: &Config) {
+        self.coins = config.coins.clone();
+        self.feeds = config.feeds().into_iter().map(|(_, symbol)| symbol).collect();
+        self.stale_after = config.appearance.stale_after;
+        self.portfolio = config.portfolio.clone();
+        self.display_currency = config.appearance.display_currency.clone();
+        self.numbers = config.numbers.clone();
+        self.spans = config.appearance.spans.clone();
+
+        // Keep cooldowns and hysteresis state unless the alerts changed
+        if self.alerts.iter().map(|a| &a.config).ne(config.alerts.iter()) {
+            self.alerts = config.alerts.iter().cloned().map(Alert::new).collect();
+        }
+
+        let feeds = &self.feeds;
+        let keep = |symbol: &String| feeds.contains(symbol);
+        self.prices.retain(|symbol, _| keep(symbol));
+        self.pending_opens.retain(|symbol, _| keep(symbol));
+        self.history.retain(|symbol, _| keep(symbol));
+        self.flashing.retain(|symbol, _| keep(symbol));
+        self.rebuild_segments();
+        // The new appearance (fonts, colours) may draw even unchanged segments differently
+        self.revision += 1;
+    }
+
+    /// Current prices of every shown coin, for `ctl prices`.
+    pub fn prices_json(&self) -> serde_json::Value {
+        let coins: Vec<serde_json::Value> = self.coins.iter()
+            .map(|coin| {
+                let data = self.prices.get(&coin.symbol);
+                let priced = data.map(|d| self.priced(coin, d));
+                serde_json::json!({
+                    "symbol": coin.symbol,
+                    "name": coin.name,
+                    "source": coin.source.name(),
+                    "price": data.map(|d| d.price),
+                    "open_24h": data.map(|d| d.open_24h),
+                    "change_pct": data.and_then(Self::change_pct),
+                    "bid": data.and_then(|d| d.stats.bid),
+                    "ask": data.and_then(|d| d.stats.ask),
+                    "high": data.and_then(|d| d.stats.high),
+                    "low": data.and_then(|d| d.stats.low),
+                    "vwap": data.and_then(|d| d.stats.vwap),
+                    "volume": data.and_then(|d| d.stats.volume),
+                    "age_secs": data.map(|d| d.updated.elapsed().as_secs_f64()),
+                    "amount": coin.amount,
+                    "value": priced.as_ref().and_then(|p| Self::position_value(coin, p)),
+                    "pnl": priced.as_ref().and_then(|p| Self::pnl(coin, p)),
+                    "value_currency": priced.as_ref().map(|p| p.currency),
+                })
+            })
+            .collect();
+
+        let portfolio = self.portfolio_totals().map(|(value, open)| serde_json::json!({
+            "value": value,
+            "change_24h": value - open,
+            "currency": self.total_currency(),
+        }));
+
+        serde_json::json!({
+            "connection": format!("{:?}", self.connection_state()).to_lowercase(),
+            "coins": coins,
+            "portfolio": portfolio,
+        })
+    }
+
+    /// Every known figure for a coin (or the portfolio total), one per
+    /// line, for the overlay's hover tooltip.
+    pub fn details(&self, symbol: &str) -> Option<String> {
+        if symbol == TOTAL_SYMBOL {
+            return self.total_details();
+        }
+        let coin = self.coins.iter().find(|c| c.symbol == symbol)?;
+        let data = self.prices.get(symbol)?;
+        let priced = self.priced(coin, data);
+        let data = &priced.data;
+        let money = |v: f64| self.format_money(v, priced.currency, coin.precision);
+        let blur = |v: String| if self.portfolio.blur { BLURRED.to_string() } else { v };
+
+        let mut lines = vec![
+            format!("{} ({}) on {}", coin.name, coin.symbol, coin.source.name()),
+            format!("Price: {} ({})", money(data.price), self.format_percent(Self::change_pct(data))),
+            format!("24h open: {}", money(data.open_24h)),
+        ];
+        if let (Some(bid), Some(ask)) = (data.stats.bid, data.stats.ask) {
+            lines.push(format!("Bid / ask: {} / {}", money(bid), money(ask)));
+        }
+        if let (Some(low), Some(high)) = (data.stats.low, data.stats.high) {
+            lines.push(format!("24h range: {} – {}", money(low), money(high)));
         }
+        if let Some(vwap) = data.stats.vwap {
+            lines.push(format!("VWAP: {}", money(vwap)));
+        }
+        if let Some(volume) = data.stats.volume {
+            lines.push(format!("24h volume: {}", self.format_volume(volume)));
+        }
+        if let (Some(amount), Some(value)) = (coin.amount, Self::position_value(coin, &priced)) {
+            lines.push(format!(
+                "Holding: {} = {}",
+                blur(self.numbers.exact(amount)),
+                blur(self.format_money(value, priced.currency, Precision::Auto)),
+            ));
+        }
+        if let Some(pnl) = Self::pnl(coin, &priced) {
+            lines.push(format!("P&L: {}", blur(self.format_signed_price(pnl, priced.currency, Precision::Auto))));
+        }
+        if let Some(tick) = data.last_tick {
+            let arrow = if tick.direction == Direction::Up { "▲" } else { "▼" };
+            lines.push(format!(
+                "Last move: {} from {}, {} ago",
+                arrow,
+                money(tick.previous),
+                Self::format_age(tick.at.elapsed()),
+            ));
+        }
+        lines.push(format!("Updated {} ago", Self::format_age(data.updated.elapsed())));
+
+        Some(lines.join("\n"))
+    }
+
+    /// Each holding's value and the portfolio total, for the tooltip.
+    fn total_details(&self) -> Option<String> {
+        let (value, open) = self.portfolio_totals()?;
+        let currency = self.total_currency().unwrap_or_default();
+        let blur = |v: String| if self.portfolio.blur { BLURRED.to_string() } else { v };
+        let change = (open > 0.0).then(|| (value - open) / open * 100.0);
+
+        let mut lines = vec![format!(
+            "Portfolio: {} ({})",
+            blur(self.format_money(value, currency, Precision::Auto)),
+            self.format_percent(change),
+        )];
+        for coin in &self.coins {
+            let holding = self.prices.get(&coin.symbol)
+                .map(|data| self.priced(coin, data))
+                .and_then(|priced| Some((Self::position_value(coin, &priced)?, priced.currency)));
+            if let Some((value, currency)) = holding {
+                lines.push(format!("{}: {}", coin.name, blur(self.format_money(value, currency, Precision::Auto))));
+            }
+        }
+
+        Some(lines.join("\n"))
     }
 
-    /// Update the current price for a symbol.
-    pub fn update_price(&mut self, symbol: &str, price: f64) {
+    /// Update the current price, and the 24h open and stats when the tick
+    /// carries them, for a symbol.
+    pub fn update_price(&mut self, symbol: &str, price: f64, open: Option<f64>, stats: &MarketStats) {
         if let Some(data) = self.prices.get_mut(symbol) {
+            // Ticks that only update stats keep the last move's flash going
+            if price != data.price {
+                data.last_tick = Some(Tick {
+                    previous: data.price,
+                    direction: if price > data.price { Direction::Up } else { Direction::Down },
+                    at: Instant::now(),
+                });
+            }
             data.price = price;
+            if let Some(open) = open {
+                data.open_24h = open;
+            }
+            data.stats.merge(stats);
+            data.updated = Instant::now();
         } else {
+            let pending = self.pending_opens.remove(symbol);
             self.prices.insert(symbol.to_string(), CoinData {
                 price,
-                open_24h: price,
+                open_24h: open.or(pending).unwrap_or(price),
+                stats: *stats,
+                updated: Instant::now(),
+                last_tick: None,
             });
         }
+        self.record_history(symbol, Instant::now(), price);
+        self.check_alerts(symbol);
         self.rebuild_segments();
     }
 
+    /// Evaluate the alerts for a symbol against its latest price.
+    fn check_alerts(&mut self, symbol: &str) {
+        let data = match self.prices.get(symbol) {
+            Some(data) => data,
+            None => return,
+        };
+        let now = Instant::now();
+
+        // Alert levels are in the pair's own quote currency
+        let quote = currency::quote_of(symbol);
+        let precision = self.coins.iter()
+            .find(|c| c.symbol == symbol)
+            .map_or(Precision::Auto, |c| c.precision);
+        let numbers = &self.numbers;
+        let format_price = |price| currency::format_money(price, quote, numbers, precision);
+
+        for alert in self.alerts.iter_mut().filter(|a| a.config.symbol == symbol) {
+            if let Some(description) = alert.check(data.price, data.open_24h, now, format_price, numbers) {
+                let coin = self.coins.iter().find(|c| c.symbol == symbol);
+                let name = coin.map(|c| c.name.as_str()).unwrap_or(symbol);
+                let change = Self::change_pct(data)
+                    .map(|c| format!(" ({} 24h)", numbers.percent(c)))
+                    .unwrap_or_default();
+
+                self.alert_events.push(AlertEvent {
+                    symbol: symbol.to_string(),
+                    summary: format!("{} {}", name, description),
+                    body: format!("{} is now {}{}", symbol, format_price(data.price), change),
+                    icon: coin.map(|c| c.icon.clone()).unwrap_or_default(),
+                });
+                self.flashing.insert(symbol.to_string(), now + ALERT_FLASH);
+            }
+        }
+    }
+
+    /// Collect alerts fired since the last call, for notification.
+    pub fn take_alert_events(&mut self) -> Vec<AlertEvent> {
+        std::mem::take(&mut self.alert_events)
+    }
+
+    /// Sparkline window for a symbol, if its coin has one enabled.
+    fn sparkline_window(&self, symbol: &str) -> Option<Duration> {
+        self.coins.iter()
+            .find(|c| c.symbol == symbol)
+            .and_then(|c| c.sparkline.as_ref())
+            .map(|s| s.window)
+    }
+
+    /// Append a price to the sparkline history, merging points that fall
+    /// in the same time bucket and dropping those older than the window.
+    fn record_history(&mut self, symbol: &str, at: Instant, price: f64) {
+        let window = match self.sparkline_window(symbol) {
+            Some(window) => window,
+            None => return,
+        };
+        let bucket = window / SPARKLINE_POINTS;
+        let points = self.history.entry(symbol.to_string()).or_default();
+
+        match points.back_mut() {
+            Some(last) if at.saturating_duration_since(last.0) < bucket => last.1 = price,
+            _ => points.push_back((at, price)),
+        }
+
+        while points.front().is_some_and(|(t, _)| t.elapsed() > window) {
+            points.pop_front();
+        }
+    }
+
+    /// Seed sparkline history with older prices (e.g. from an OHLC endpoint),
+    /// keeping any points already recorded from the live feed.
+    pub fn backfill_history(&mut self, symbol: &str, points: &[(SystemTime, f64)]) {
+        let live = self.history.remove(symbol).unwrap_or_default();
+        let first_live = live.front().map(|(t, _)| *t);
+
+        for (time, price) in points {
+            let age = SystemTime::now().duration_since(*time).unwrap_or_default();
+            if let Some(at) = Instant::now().checked_sub(age) {
+                if first_live.is_none_or(|first| at < first) {
+                    self.record_history(symbol, at, *price);
+                }
+            }
+        }
+
+        let points = self.history.entry(symbol.to_string()).or_default();
+        points.extend(live);
+        self.rebuild_segments();
+    }
+
+    /// Normalise a coin's history into sparkline points.
+    fn sparkline_for(&self, coin: &CoinConfig) -> Option<Sparkline> {
+        let config = coin.sparkline.as_ref()?;
+        let history = self.history.get(&coin.symbol)?;
+        if history.len() < 2 {
+            return None;
+        }
+
+        let (low, high) = history.iter()
+            .fold((f64::MAX, f64::MIN), |(lo, hi), (_, p)| (lo.min(*p), hi.max(*p)));
+        let range = high - low;
+
+        Some(Sparkline {
+            points: history.iter()
+                .map(|(_, p)| if range > 0.0 { (p - low) / range } else { 0.5 })
+                .collect(),
+            width: config.width,
+            color: config.color,
+        })
+    }
+
+    /// Re-evaluate time-based display state. Call periodically (about once
+    /// a second) so coins go stale even when no updates arrive.
+    pub fn refresh(&mut self) {
+        let now = Instant::now();
+        self.flashing.retain(|_, until| *until > now);
+
+        if self.showing_stale || self.prices.values().any(|d| self.is_stale(d)) {
+            self.rebuild_segments();
+        }
+    }
+
+    fn is_stale(&self, data: &CoinData) -> bool {
+        self.stale_after.is_some_and(|limit| data.updated.elapsed() >= limit)
+    }
+
+    /// Compact age like `45s`, `12m` or `3h`.
+    fn format_age(age: Duration) -> String {
+        let secs = age.as_secs();
+        if secs < 60 {
+            format!("{}s", secs)
+        } else if secs < 3600 {
+            format!("{}m", secs / 60)
+        } else {
+            format!("{}h", secs / 3600)
+        }
+    }
+
     /// Set the 24h open price for calculating change percentage.
     pub fn set_open_price(&mut self, symbol: &str, open: f64) {
         if let Some(data) = self.prices.get_mut(symbol) {
             data.open_24h = open;
             self.rebuild_segments();
+        } else {
+            self.pending_opens.insert(symbol.to_string(), open);
         }
     }
 
-    fn get_change(&self, symbol: &str) -> (String, Direction) {
-        if let Some(data) = self.prices.get(symbol) {
-            if data.open_24h > 0.0 {
-                let change = ((data.price - data.open_24h) / data.open_24h) * 100.0;
-                if change > 0.01 {
-                    return (format!("+{:.1}%▲", change), Direction::Up);
-                } else if change < -0.01 {
-                    return (format!("{:.1}%▼", change), Direction::Down);
-                } else {
-                    return (format!("{:.1}%", change), Direction::Neutral);
-                }
-            }
+    /// Record the connection state of a price source.
+    pub fn set_connection_state(&mut self, source: &str, state: ConnectionState) {
+        self.connections.insert(source.to_string(), state);
+    }
+
+    /// Forget all sources' states, e.g. before they are restarted.
+    pub fn clear_connection_states(&mut self) {
+        self.connections.clear();
+    }
+
+    /// Overall connection state: the least healthy of all sources.
+    pub fn connection_state(&self) -> ConnectionState {
+        self.connections.values()
+            .copied()
+            .max()
+            .unwrap_or(ConnectionState::Connecting)
+    }
+
+    /// 24h change as a percentage, if the open is known.
+    fn change_pct(data: &CoinData) -> Option<f64> {
+        (data.open_24h > 0.0).then(|| ((data.price - data.open_24h) / data.open_24h) * 100.0)
+    }
+
+    fn direction(change: Option<f64>) -> Direction {
+        match change {
+            Some(c) if c > 0.01 => Direction::Up,
+            Some(c) if c < -0.01 => Direction::Down,
+            _ => Direction::Neutral,
         }
-        ("--".to_string(), Direction::Neutral)
     }
 
-    fn format_price(price: f64) -> String {
-        if price >= 1000.0 {
-            format!("${:.0}", price)
-        } else if price >= 1.0 {
-            format!("${:.2}", price)
-        } else if price >= 0.01 {
-            format!("${:.4}", price)
-        } else {
-            format!("${:.6}", price)
+    /// Percentage change with a direction arrow, e.g. `+1.2%▲`.
+    fn format_change(&self, change: Option<f64>) -> String {
+        match (change, Self::direction(change)) {
+            (Some(c), Direction::Up) => format!("{}▲", self.numbers.percent(c)),
+            (Some(c), Direction::Down) => format!("{}▼", self.numbers.percent(c)),
+            (Some(c), _) => self.numbers.percent_plain(c),
+            (None, _) => "--".to_string(),
+        }
+    }
+
+    /// Signed percentage, or `--` when unknown.
+    fn format_percent(&self, percent: Option<f64>) -> String {
+        percent
+            .map(|p| self.numbers.percent(p))
+            .unwrap_or_else(|| "--".to_string())
+    }
+
+    fn format_money(&self, amount: f64, currency: &str, precision: Precision) -> String {
+        currency::format_money(amount, currency, &self.numbers, precision)
+    }
+
+    /// Price of one `from` in `to`, from a streamed pair of the two.
+    fn rate(&self, from: &str, to: &str) -> Option<f64> {
+        if from == to {
+            return Some(1.0);
         }
+
+        let direct = self.prices.get(&format!("{}/{}", from, to)).map(|d| d.price);
+        let inverse = self.prices.get(&format!("{}/{}", to, from)).map(|d| d.price);
+        direct.or(inverse.filter(|p| *p > 0.0).map(|p| 1.0 / p))
+    }
+
+    /// A coin's figures in `display_currency` once its rate is known, and
+    /// in its quote currency until then (or when none is set).
+    fn priced<'a>(&'a self, coin: &'a CoinConfig, data: &CoinData) -> Priced<'a> {
+        let quote = currency::quote_of(&coin.symbol);
+        let (data, currency) = match &self.display_currency {
+            Some(target) => match self.rate(quote, target) {
+                Some(rate) => (data.converted(rate), target.as_str()),
+                None => (data.clone(), quote),
+            },
+            None => (data.clone(), quote),
+        };
+
+        // The cost basis is in the currency prices are meant to show in
+        let cost_currency = self.display_currency.as_deref().unwrap_or(quote);
+        Priced {
+            data,
+            currency,
+            cost_basis: coin.cost_basis.filter(|_| currency == cost_currency),
+        }
+    }
+
+    /// Market value of a coin's holding.
+    fn position_value(coin: &CoinConfig, priced: &Priced) -> Option<f64> {
+        coin.amount.map(|amount| amount * priced.data.price)
+    }
+
+    /// Unrealized profit or loss of a holding against its cost basis.
+    fn pnl(coin: &CoinConfig, priced: &Priced) -> Option<f64> {
+        Some(Self::position_value(coin, priced)? - priced.cost_basis?)
+    }
+
+    /// Currency the portfolio total is shown in: `display_currency`, or
+    /// else the quote currency of the first holding.
+    fn total_currency(&self) -> Option<&str> {
+        self.display_currency.as_deref().or_else(|| {
+            self.coins.iter()
+                .find(|c| c.amount.is_some())
+                .map(|c| currency::quote_of(&c.symbol))
+        })
+    }
+
+    /// Value of every priced holding now and at the 24h opens, in
+    /// `total_currency`, if there are any. Holdings that can't be
+    /// converted yet are left out.
+    fn portfolio_totals(&self) -> Option<(f64, f64)> {
+        let target = self.total_currency()?;
+        let holdings: Vec<(f64, f64)> = self.coins.iter()
+            .filter_map(|coin| {
+                let data = self.prices.get(&coin.symbol)?;
+                let amount = coin.amount?;
+                let rate = self.rate(currency::quote_of(&coin.symbol), target)?;
+                Some((amount * data.price * rate, amount * data.open_24h * rate))
+            })
+            .collect();
+
+        (!holdings.is_empty()).then(|| {
+            holdings.iter().fold((0.0, 0.0), |(value, open), (v, o)| (value + v, open + o))
+        })
+    }
+
+    /// A price difference with its sign, e.g. `+$812` or `-$0.42`.
+    fn format_signed_price(&self, amount: f64, currency: &str, precision: Precision) -> String {
+        let sign = if amount < 0.0 { '-' } else { '+' };
+        format!("{}{}", sign, self.format_money(amount.abs(), currency, precision))
+    }
+
+    fn format_volume(&self, volume: f64) -> String {
+        let decimals = if volume >= 1000.0 { 0 } else { 2 };
+        self.numbers.number(volume, Precision::Decimals(decimals))
+    }
+
+    /// Expand a display template such as `"{price} {change}"` for one coin.
+    ///
+    /// Unknown placeholders are left as written; known fields the exchange
+    /// hasn't sent (or holdings the coin doesn't have) render as `--`.
+    fn render_template(&self, coin: &CoinConfig, priced: &Priced) -> Rendered {
+        let data = &priced.data;
+        let money = |v: f64| self.format_money(v, priced.currency, coin.precision);
+        let change = Self::change_pct(data);
+        let price = |v: Option<f64>| v.map(money).unwrap_or_else(|| "--".to_string());
+        let holding = |v: Option<String>| match v {
+            Some(_) if self.portfolio.blur => BLURRED.to_string(),
+            Some(v) => v,
+            None => "--".to_string(),
+        };
+        let pnl = Self::pnl(coin, priced);
+
+        expand_placeholders(&coin.format, &self.spans, |key| {
+            Some(match key {
+                "symbol" => coin.symbol.clone(),
+                "name" => coin.name.clone(),
+                "price" => money(data.price),
+                "change" => self.format_change(change),
+                "change_pct" => self.format_percent(change),
+                "change_abs" => self.format_signed_price(data.price - data.open_24h, priced.currency, coin.precision),
+                "bid" => price(data.stats.bid),
+                "ask" => price(data.stats.ask),
+                "high" => price(data.stats.high),
+                "low" => price(data.stats.low),
+                "vwap" => price(data.stats.vwap),
+                "volume" => data.stats.volume
+                    .map(|v| self.format_volume(v))
+                    .unwrap_or_else(|| "--".to_string()),
+                "amount" => holding(coin.amount.map(|a| self.numbers.exact(a))),
+                "value" => holding(Self::position_value(coin, priced)
+                    .map(|v| self.format_money(v, priced.currency, Precision::Auto))),
+                "pnl" => holding(pnl.map(|p| self.format_signed_price(p, priced.currency, Precision::Auto))),
+                "pnl_pct" => self.format_percent(pnl
+                    .zip(priced.cost_basis)
+                    .filter(|(_, cost)| *cost > 0.0)
+                    .map(|(pnl, cost)| pnl / cost * 100.0)),
+                _ => return None,
+            })
+        })
+    }
+
+    /// Expand `portfolio.total_format` for the summed holdings.
+    fn render_total(&self, value: f64, open: f64) -> Rendered {
+        let currency = self.total_currency().unwrap_or_default();
+        let change = (open > 0.0).then(|| (value - open) / open * 100.0);
+        let masked = |v: String| if self.portfolio.blur { BLURRED.to_string() } else { v };
+
+        expand_placeholders(&self.portfolio.total_format, &self.spans, |key| {
+            Some(match key {
+                "value" => masked(self.format_money(value, currency, Precision::Auto)),
+                "change" => self.format_change(change),
+                "change_pct" => self.format_percent(change),
+                "change_abs" => masked(self.format_signed_price(value - open, currency, Precision::Auto)),
+                _ => return None,
+            })
+        })
+    }
+
+    /// Changes whenever the segments would draw differently (text, colour,
+    /// sparkline, ...), so renderers can tell when their drawing is out of
+    /// date.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Changes whenever a segment's tick or alert flash does, which
+    /// renderers draw over the segments without redrawing them.
+    pub fn flash_revision(&self) -> u64 {
+        self.flash_revision
     }
 
     fn rebuild_segments(&mut self) {
-        self.segments.clear();
+        let previous = std::mem::take(&mut self.segments);
+        self.build_segments();
+
+        let same_len = previous.len() == self.segments.len();
+        let pairs = || previous.iter().zip(&self.segments);
+        if !same_len || !pairs().all(|(old, new)| old.looks_like(new)) {
+            self.revision += 1;
+        }
+        if !same_len || !pairs().all(|(old, new)| old.tick == new.tick && old.flash_until == new.flash_until) {
+            self.flash_revision += 1;
+        }
+    }
+
+    fn build_segments(&mut self) {
+        self.showing_stale = false;
 
+        let totals = self.portfolio_totals().filter(|_| self.portfolio.show_total);
         let active_count = self.coins.iter()
             .filter(|c| self.prices.contains_key(&c.symbol))
-            .count();
+            .count() + totals.is_some() as usize;
+
+        if let Some((value, open)) = totals {
+            let change_pct = (open > 0.0).then(|| (value - open) / open * 100.0);
+            let Rendered { text, markup } = self.render_total(value, open);
+            self.segments.push(Segment {
+                symbol: Some(TOTAL_SYMBOL.to_string()),
+                text,
+                markup,
+                direction: Self::direction(change_pct),
+                icon: None,
+                sparkline: None,
+                flash_until: None,
+                change_pct,
+                tick: None,
+            });
+
+            if active_count > 1 {
+                self.segments.push(Segment::separator());
+            }
+        }
 
         for coin in &self.coins {
             if let Some(data) = self.prices.get(&coin.symbol) {
-                let (change_str, direction) = self.get_change(&coin.symbol);
-                let price_str = Self::format_price(data.price);
+                let change_pct = Self::change_pct(data);
+                let mut direction = Self::direction(change_pct);
+                let Rendered { mut text, mut markup } = self.render_template(coin, &self.priced(coin, data));
+
+                // Dim frozen prices and show how old they are
+                if self.is_stale(data) {
+                    direction = Direction::Stale;
+                    let age = format!(" {}", Self::format_age(data.updated.elapsed()));
+                    text.push_str(&age);
+                    markup.push_str(&age);
+                    self.showing_stale = true;
+                }
 
                 self.segments.push(Segment {
-                    text: format!("{} {}", price_str, change_str),
+                    symbol: Some(coin.symbol.clone()),
+                    text,
+                    markup,
                     direction,
                     icon: Some(coin.icon.clone()),
+                    sparkline: self.sparkline_for(coin),
+                    flash_until: self.flashing.get(&coin.symbol)
+                        .copied()
+                        .filter(|until| *until > Instant::now()),
+                    change_pct,
+                    tick: data.last_tick.filter(|_| direction != Direction::Stale),
                 });
 
                 if active_count > 1 {
-                    self.segments.push(Segment {
-                        text: SEPARATOR.to_string(),
-                        direction: Direction::Neutral,
-                        icon: None,
-                    });
+                    self.segments.push(Segment::separator());
+                }
+            }
+        }
+    }
+}
+
+/// An expanded template, as plain text and as Pango markup.
+struct Rendered {
+    text: String,
+    markup: String,
+}
+
+impl Rendered {
+    /// Append text, in `font` (a Pango font description) if given.
+    fn push(&mut self, text: &str, font: Option<&String>) {
+        self.text.push_str(text);
+        match font {
+            Some(font) => self.markup.push_str(&format!(
+                "<span font=\"{}\">{}</span>",
+                escape_markup(font),
+                escape_markup(text),
+            )),
+            None => self.markup.push_str(&escape_markup(text)),
+        }
+    }
+}
+
+/// Replace each `{key}` in `template` with `lookup(key)`, leaving the
+/// placeholder untouched when the lookup returns `None`. In the markup,
+/// values whose key has a font in `spans` are wrapped in a span of it.
+fn expand_placeholders(
+    template: &str,
+    spans: &HashMap<String, String>,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Rendered {
+    let mut out = Rendered {
+        text: String::with_capacity(template.len()),
+        markup: String::with_capacity(template.len()),
+    };
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        out.push(&rest[..start], None);
+        let after = &rest[start + 1..];
+
+        match after.find('}') {
+            Some(end) => {
+                let key = &after[..end];
+                match lookup(key) {
+                    Some(value) => out.push(&value, spans.get(key)),
+                    None => out.push(&rest[start..start + end + 2], None),
                 }
+                rest = &after[end + 1..];
+            }
+            None => {
+                out.push(&rest[start..], None);
+                rest = "";
             }
         }
     }
+
+    out.push(rest, None);
+    out
+}
+
+/// Escape text for Pango markup, including attribute values.
+pub fn escape_markup(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(config: &str) -> TickerState {
+        TickerState::new(&Config::parse(config).unwrap())
+    }
+
+    #[test]
+    fn revision_changes_only_with_the_look() {
+        let mut state = state(r#"
+            [numbers]
+            locale = "en_US"
+
+            [[coins]]
+            symbol = "BTC/USD"
+            name = "Bitcoin"
+            icon = "btc.svg"
+            format = "{symbol} {price}"
+        "#);
+        let stats = MarketStats::default();
+        state.update_price("BTC/USD", 67000.0, Some(66000.0), &stats);
+        let (revision, flashes) = (state.revision(), state.flash_revision());
+
+        // An update that doesn't move the price changes nothing
+        state.update_price("BTC/USD", 67000.0, None, &stats);
+        assert_eq!((state.revision(), state.flash_revision()), (revision, flashes));
+
+        // A move too small to show only flashes
+        state.update_price("BTC/USD", 67000.001, None, &stats);
+        assert_eq!(state.revision(), revision);
+        assert_ne!(state.flash_revision(), flashes);
+
+        state.update_price("BTC/USD", 67100.0, None, &stats);
+        assert_ne!(state.revision(), revision);
+    }
+
+    fn text<'a>(state: &'a TickerState, symbol: &str) -> &'a str {
+        state.segments.iter()
+            .find(|s| s.symbol.as_deref() == Some(symbol))
+            .map(|s| s.text.as_str())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn holdings_show_value_and_pnl() {
+        let mut state = state(r#"
+            [numbers]
+            locale = "en_US"
+
+            [[coins]]
+            symbol = "BTC/USD"
+            name = "Bitcoin"
+            icon = "btc.svg"
+            format = "{amount} {value} {pnl} {pnl_pct}"
+            amount = 0.5
+            cost_basis = 30000
+        "#);
+        state.update_price("BTC/USD", 67000.0, Some(66000.0), &MarketStats::default());
+        assert_eq!(text(&state, "BTC/USD"), "0.5 $33,500 +$3,500 +11.7%");
+
+        state.portfolio.blur = true;
+        state.rebuild_segments();
+        assert_eq!(text(&state, "BTC/USD"), "••••• ••••• ••••• +11.7%");
+    }
+
+    #[test]
+    fn converts_through_streamed_rates() {
+        let mut state = state(r#"
+            [appearance]
+            display_currency = "USD"
+
+            [numbers]
+            locale = "en_US"
+
+            [portfolio]
+            total_format = "TOTAL {value} {change_abs} {change_pct}"
+
+            [[coins]]
+            symbol = "BTC/USD"
+            name = "Bitcoin"
+            icon = "btc.svg"
+            format = "{price}"
+            amount = 0.5
+
+            [[coins]]
+            symbol = "ETH/EUR"
+            name = "Ether"
+            icon = "eth.svg"
+            format = "{price} {pnl}"
+            amount = 2
+            cost_basis = 4000
+
+            [[rates]]
+            symbol = "EUR/USD"
+        "#);
+        let stats = MarketStats::default();
+        state.update_price("BTC/USD", 60000.0, Some(50000.0), &stats);
+        state.update_price("ETH/EUR", 2000.0, Some(2500.0), &stats);
+
+        // Until EUR/USD is known, ETH stays in euros and out of the total,
+        // and its cost basis (in dollars) can't be compared
+        assert_eq!(text(&state, "ETH/EUR"), "€2,000 --");
+        assert_eq!(text(&state, TOTAL_SYMBOL), "TOTAL $30,000 +$5,000 +20.0%");
+
+        state.update_price("EUR/USD", 1.1, Some(1.0), &stats);
+        assert_eq!(text(&state, "ETH/EUR"), "$2,200 +$400.00");
+        assert_eq!(text(&state, TOTAL_SYMBOL), "TOTAL $34,400 +$3,900 +12.8%");
+        assert!(state.segments[0].is_total());
+    }
+
+    #[test]
+    fn total_is_in_the_first_holdings_quote() {
+        let mut state = state(r#"
+            [numbers]
+            locale = "en_US"
+
+            [[coins]]
+            symbol = "SOL/BTC"
+            name = "Solana"
+            icon = "sol.svg"
+            amount = 10
+
+            [[coins]]
+            symbol = "ETH/BTC"
+            name = "Ether"
+            icon = "eth.svg"
+            amount = 1
+        "#);
+        let stats = MarketStats::default();
+        state.update_price("SOL/BTC", 0.002, Some(0.002), &stats);
+        state.update_price("ETH/BTC", 0.05, Some(0.04), &stats);
+
+        assert_eq!(state.total_currency(), Some("BTC"));
+        let (value, open) = state.portfolio_totals().unwrap();
+        assert!((value - 0.07).abs() < 1e-9, "{}", value);
+        assert!((open - 0.06).abs() < 1e-9, "{}", open);
+    }
 }
@@ -0,0 +1,630 @@
+//! Config file validation.
+//!
+//! Finds the mistakes that loading otherwise papers over with defaults
+//! (unknown keys, bad colours, unknown anchors, ...) and reports each with
+//! its line and column. Used for startup warnings and `check-config`.
+
+use crate::config::{parse_hex_color, Config, ConfigFile, Source};
+use crate::number::MAX_DECIMALS;
+use crate::websocket;
+use std::collections::HashSet;
+use std::fmt;
+use std::ops::Range;
+use std::path::Path;
+use toml_edit::{ImDocument, Item};
+
+const ANIMATION_MODES: [&str; 4] = ["scroll", "page", "static", "roll"];
+const ANCHORS: [&str; 7] = ["top-left", "top-right", "bottom-left", "bottom-right", "top", "bottom", "center"];
+const CONDITIONS: [&str; 4] = ["above", "below", "move", "cross_open"];
+const TICK_FLASHES: [&str; 3] = ["background", "text", "none"];
+const PLACEHOLDERS: [&str; 16] = [
+    "symbol", "name", "price", "change", "change_pct", "change_abs", "bid", "ask",
+    "high", "low", "vwap", "volume", "amount", "value", "pnl", "pnl_pct",
+];
+const WHEEL_ACTIONS: [&str; 3] = ["nudge", "speed", "none"];
+const PROXY_SCHEMES: [&str; 3] = ["http", "socks5", "socks5h"];
+
+/// A problem found in the config file.
+#[derive(Debug)]
+pub struct Problem {
+    /// 1-based position of the offending key or value.
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+/// One step of a path into the document, like `coins` or `[2]`.
+#[derive(Clone, Debug)]
+enum Step {
+    Key(String),
+    Index(usize),
+}
+
+impl Step {
+    fn key(key: &str) -> Self {
+        Step::Key(key.to_string())
+    }
+}
+
+/// Which part of a `key = value` pair a problem points at.
+#[derive(Clone, Copy)]
+enum Target {
+    Key,
+    Value,
+}
+
+struct Checker<'a> {
+    contents: &'a str,
+    doc: ImDocument<&'a str>,
+    problems: Vec<Problem>,
+}
+
+/// Check config file contents, returning every problem found.
+pub fn check(contents: &str) -> Vec<Problem> {
+    // Unknown keys, plus syntax and type errors (which stop deserialization)
+    let mut unknown = Vec::new();
+    let parsed: Result<ConfigFile, _> = serde_ignored::deserialize(
+        toml::Deserializer::new(contents),
+        |path| unknown.push(path_steps(&path)),
+    );
+
+    if let Err(e) = parsed {
+        let offset = e.span().map(|s| s.start).unwrap_or(0);
+        let (line, column) = line_column(contents, offset);
+        return vec![Problem { line, column, message: e.message().to_string() }];
+    }
+
+    let doc = match ImDocument::parse(contents) {
+        Ok(doc) => doc,
+        Err(e) => return vec![Problem { line: 1, column: 1, message: e.to_string() }],
+    };
+    let mut checker = Checker { contents, doc, problems: Vec::new() };
+
+    for path in unknown {
+        let name = match path.last() {
+            Some(Step::Key(key)) => key.clone(),
+            _ => continue,
+        };
+        checker.report(&path, Target::Key, format!("unknown key '{}'", name));
+    }
+
+    checker.check_values();
+    checker.problems.sort_by_key(|p| (p.line, p.column));
+    checker.problems
+}
+
+/// Run `check-config`: print every problem in the file at `path` (the
+/// user config by default), returning whether it is clean.
+pub fn run_check(path: Option<&Path>, online: bool) -> bool {
+    let path = path.map(Path::to_path_buf).unwrap_or_else(Config::config_path);
+
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) => {
+            eprintln!("{}: {}", path.display(), e);
+            return false;
+        }
+    };
+
+    let mut problems = check(&contents);
+    if online && problems.is_empty() {
+        if let Ok(config) = Config::parse(&contents) {
+            problems = check_pairs(&contents, &config);
+        }
+    }
+
+    for problem in &problems {
+        println!("{}:{}", path.display(), problem);
+    }
+
+    match problems.len() {
+        0 => println!("{}: OK", path.display()),
+        1 => println!("1 problem found"),
+        n => println!("{} problems found", n),
+    }
+    problems.is_empty()
+}
+
+/// Ask each exchange whether the configured pairs exist (blocking).
+pub fn check_pairs(contents: &str, config: &Config) -> Vec<Problem> {
+    let doc = match ImDocument::parse(contents) {
+        Ok(doc) => doc,
+        Err(_) => return Vec::new(),
+    };
+    let mut checker = Checker { contents, doc, problems: Vec::new() };
+
+    for source in websocket::sources_for(config) {
+        let unknown = match source.unknown_symbols() {
+            Ok(unknown) => unknown,
+            Err(e) => {
+                checker.problems.push(Problem {
+                    line: 1,
+                    column: 1,
+                    message: format!("couldn't check pairs with {}: {}", source.name(), e),
+                });
+                continue;
+            }
+        };
+
+        for symbol in unknown {
+            let coin = checker.coin_symbols().iter().position(|s| *s == symbol);
+            let rate = checker.rate_symbols().iter().position(|s| *s == symbol);
+            let (path, hint) = match (coin, rate) {
+                (Some(i), _) => (vec![Step::key("coins"), Step::Index(i), Step::key("symbol")], ""),
+                (None, Some(i)) => (vec![Step::key("rates"), Step::Index(i), Step::key("symbol")], ""),
+                // A rate pair picked automatically for display_currency
+                (None, None) => (
+                    vec![Step::key("appearance"), Step::key("display_currency")],
+                    " (needed for display_currency; list a pair it does in [[rates]])",
+                ),
+            };
+            checker.report(&path, Target::Value, format!("{} doesn't list '{}'{}", source.name(), symbol, hint));
+        }
+    }
+
+    checker.problems.sort_by_key(|p| (p.line, p.column));
+    checker.problems
+}
+
+impl Checker<'_> {
+    fn check_values(&mut self) {
+        self.check_anchor(&[Step::key("position"), Step::key("anchor")]);
+        self.check_exclusive(&[Step::key("position")]);
+
+        for key in ["color_up", "color_down", "color_neutral", "color_stale"] {
+            self.check_color(&[Step::key("appearance"), Step::key(key)], false);
+        }
+        self.check_color(&[Step::key("sparkline"), Step::key("color")], true);
+
+        let fps_path = [Step::key("animation"), Step::key("fps")];
+        if let Some(fps) = self.item(&fps_path).and_then(Item::as_integer) {
+            if !(1..=120).contains(&fps) {
+                self.report(&fps_path, Target::Value, format!("fps {} is out of range (1-120)", fps));
+            }
+        }
+
+        let flash_path = [Step::key("appearance"), Step::key("tick_flash")];
+        if let Some(flash) = self.str_at(&flash_path) {
+            if !TICK_FLASHES.contains(&flash.as_str()) {
+                self.report(
+                    &flash_path,
+                    Target::Value,
+                    format!("unknown tick flash '{}' (expected one of {})", flash, TICK_FLASHES.join(", ")),
+                );
+            }
+        }
+
+        let mode_path = [Step::key("animation"), Step::key("mode")];
+        if let Some(mode) = self.str_at(&mode_path) {
+            if !ANIMATION_MODES.contains(&mode.as_str()) {
+                self.report(
+                    &mode_path,
+                    Target::Value,
+                    format!("unknown animation mode '{}' (expected one of {})", mode, ANIMATION_MODES.join(", ")),
+                );
+            }
+        }
+
+        let spans: Vec<String> = self.item(&[Step::key("appearance"), Step::key("spans")])
+            .and_then(Item::as_table_like)
+            .map(|spans| spans.iter().map(|(key, _)| key.to_string()).collect())
+            .unwrap_or_default();
+        for key in spans.into_iter().filter(|key| !PLACEHOLDERS.contains(&key.as_str())) {
+            let path = [Step::key("appearance"), Step::key("spans"), Step::Key(key.clone())];
+            self.report(&path, Target::Key, format!("unknown placeholder '{}'", key));
+        }
+
+        let wheel_path = [Step::key("mouse"), Step::key("wheel")];
+        if let Some(wheel) = self.str_at(&wheel_path) {
+            if !WHEEL_ACTIONS.contains(&wheel.as_str()) {
+                self.report(
+                    &wheel_path,
+                    Target::Value,
+                    format!("unknown wheel action '{}' (expected one of {})", wheel, WHEEL_ACTIONS.join(", ")),
+                );
+            }
+        }
+
+        let currency_path = [Step::key("appearance"), Step::key("display_currency")];
+        if let Some(currency) = self.str_at(&currency_path) {
+            if !currency.is_empty() && !currency.chars().all(|c| c.is_ascii_alphabetic()) {
+                self.report(&currency_path, Target::Value, format!("'{}' is not a currency code", currency));
+            }
+        }
+
+        self.check_digits(&[Step::key("numbers"), Step::key("percent_decimals")], 0);
+
+        // WebSocket connections tunnel through the proxy in plain TCP
+        let proxy_path = [Step::key("network"), Step::key("proxy")];
+        if let Some(proxy) = self.str_at(&proxy_path) {
+            let message = match url::Url::parse(&proxy) {
+                Ok(url) if PROXY_SCHEMES.contains(&url.scheme()) && url.host_str().is_some() => None,
+                Ok(url) if url.scheme() == "https" => {
+                    Some("https:// proxies aren't supported (use http://, socks5:// or socks5h://)".to_string())
+                }
+                _ => Some(format!("'{}' is not an http://, socks5:// or socks5h:// proxy URL", proxy)),
+            };
+            if let Some(message) = message {
+                self.report(&proxy_path, Target::Value, message);
+            }
+        }
+
+        self.check_outputs();
+        self.check_coins();
+        self.check_rates();
+        self.check_alerts();
+    }
+
+    fn check_anchor(&mut self, path: &[Step]) {
+        if let Some(anchor) = self.str_at(path) {
+            if !ANCHORS.contains(&anchor.as_str()) {
+                self.report(
+                    path,
+                    Target::Value,
+                    format!("unknown anchor '{}' (expected one of {})", anchor, ANCHORS.join(", ")),
+                );
+            }
+        }
+    }
+
+    /// An exclusive zone can only be reserved along a single edge.
+    fn check_exclusive(&mut self, table: &[Step]) {
+        let at = |key: &str| {
+            let mut path = table.to_vec();
+            path.push(Step::key(key));
+            path
+        };
+        if self.item(&at("exclusive")).and_then(Item::as_bool) != Some(true) {
+            return;
+        }
+
+        let anchor = self.str_at(&at("anchor"))
+            .or_else(|| self.str_at(&[Step::key("position"), Step::key("anchor")]))
+            .unwrap_or_else(|| "top-right".to_string());
+        if anchor != "top" && anchor != "bottom" {
+            self.report(
+                &at("exclusive"),
+                Target::Key,
+                format!("exclusive has no effect with anchor '{}' (use top or bottom)", anchor),
+            );
+        }
+    }
+
+    fn check_outputs(&mut self) {
+        let names: Vec<String> = self.item(&[Step::key("outputs")])
+            .and_then(Item::as_table_like)
+            .map(|outputs| outputs.iter().map(|(name, _)| name.to_string()).collect())
+            .unwrap_or_default();
+        let symbols = self.coin_symbols();
+
+        for name in names {
+            let output = |key: &str| vec![Step::key("outputs"), Step::Key(name.clone()), Step::key(key)];
+            self.check_anchor(&output("anchor"));
+            self.check_exclusive(&[Step::key("outputs"), Step::Key(name.clone())]);
+
+            let count = self.item(&output("coins"))
+                .and_then(Item::as_array)
+                .map(|a| a.len())
+                .unwrap_or(0);
+            for i in 0..count {
+                let mut path = output("coins");
+                path.push(Step::Index(i));
+                if let Some(symbol) = self.str_at(&path) {
+                    if !symbols.contains(&symbol) {
+                        self.report(&path, Target::Value, format!("{} isn't in [[coins]]", symbol));
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_coins(&mut self) {
+        let mut seen = HashSet::new();
+
+        for (i, symbol) in self.coin_symbols().into_iter().enumerate() {
+            let coin = |key: &str| vec![Step::key("coins"), Step::Index(i), Step::key(key)];
+
+            if !symbol.split_once('/').is_some_and(|(b, q)| !b.is_empty() && !q.is_empty()) {
+                self.report(&coin("symbol"), Target::Value, format!("'{}' is not a BASE/QUOTE pair", symbol));
+            }
+            if !seen.insert(symbol.clone()) {
+                self.report(&coin("symbol"), Target::Value, format!("{} is listed more than once", symbol));
+            }
+
+            if let Some(source) = self.str_at(&coin("source")) {
+                if Source::from_name(&source).is_none() {
+                    self.report(
+                        &coin("source"),
+                        Target::Value,
+                        format!("unknown source '{}' (expected kraken, coinbase, binance or bitstamp)", source),
+                    );
+                }
+            }
+
+            if let Some(icon) = self.str_at(&coin("icon")) {
+                if Config::find_icon(&icon).is_none() {
+                    self.report(&coin("icon"), Target::Value, format!("icon file '{}' not found", icon));
+                }
+            }
+
+            let mut color = coin("sparkline");
+            color.push(Step::key("color"));
+            self.check_color(&color, true);
+
+            let number = |item: &Item| item.as_float().or_else(|| item.as_integer().map(|i| i as f64));
+            match self.item(&coin("amount")).and_then(number) {
+                Some(amount) if amount <= 0.0 => {
+                    self.report(&coin("amount"), Target::Value, "amount must be positive".into());
+                }
+                None if self.item(&coin("cost_basis")).is_some() => {
+                    self.report(&coin("cost_basis"), Target::Key, "cost_basis has no effect without amount".into());
+                }
+                _ => {}
+            }
+
+            self.check_digits(&coin("precision"), 0);
+            self.check_digits(&coin("significant"), 1);
+            if self.item(&coin("precision")).is_some() && self.item(&coin("significant")).is_some() {
+                self.report(&coin("significant"), Target::Key, "significant has no effect with precision set".into());
+            }
+        }
+    }
+
+    fn check_rates(&mut self) {
+        let converting = self.str_at(&[Step::key("appearance"), Step::key("display_currency")])
+            .is_some_and(|c| !c.is_empty());
+
+        for (i, symbol) in self.rate_symbols().into_iter().enumerate() {
+            let rate = |key: &str| vec![Step::key("rates"), Step::Index(i), Step::key(key)];
+
+            if !converting {
+                self.report(&rate("symbol"), Target::Key, "rates have no effect without display_currency".into());
+            }
+            if !symbol.split_once('/').is_some_and(|(b, q)| !b.is_empty() && !q.is_empty()) {
+                self.report(&rate("symbol"), Target::Value, format!("'{}' is not a BASE/QUOTE pair", symbol));
+            }
+            if let Some(source) = self.str_at(&rate("source")) {
+                if Source::from_name(&source).is_none() {
+                    self.report(
+                        &rate("source"),
+                        Target::Value,
+                        format!("unknown source '{}' (expected kraken, coinbase, binance or bitstamp)", source),
+                    );
+                }
+            }
+        }
+    }
+
+    fn check_alerts(&mut self) {
+        let symbols = self.coin_symbols();
+        let count = self.item(&[Step::key("alerts")])
+            .and_then(Item::as_array_of_tables)
+            .map(|a| a.len())
+            .unwrap_or(0);
+
+        for i in 0..count {
+            let alert = |key: &str| vec![Step::key("alerts"), Step::Index(i), Step::key(key)];
+
+            if let Some(symbol) = self.str_at(&alert("symbol")) {
+                if !symbols.contains(&symbol) {
+                    self.report(&alert("symbol"), Target::Value, format!("alert for {}, which isn't in [[coins]]", symbol));
+                }
+            }
+
+            let condition = match self.str_at(&alert("condition")) {
+                Some(condition) => condition,
+                None => continue,
+            };
+            if !CONDITIONS.contains(&condition.as_str()) {
+                self.report(
+                    &alert("condition"),
+                    Target::Value,
+                    format!("unknown condition '{}' (expected one of {})", condition, CONDITIONS.join(", ")),
+                );
+            } else if condition != "cross_open" {
+                let value = self.item(&alert("value"))
+                    .and_then(|v| v.as_float().or_else(|| v.as_integer().map(|i| i as f64)));
+                match value {
+                    Some(v) if v > 0.0 => {}
+                    Some(_) => self.report(&alert("value"), Target::Value, "value must be positive".into()),
+                    None => self.report(&alert("condition"), Target::Value, format!("'{}' alert needs a value", condition)),
+                }
+            }
+        }
+    }
+
+    /// A count of decimals or significant figures, from `min` up to
+    /// `MAX_DECIMALS`.
+    fn check_digits(&mut self, path: &[Step], min: i64) {
+        let key = match path.last() {
+            Some(Step::Key(key)) => key.clone(),
+            _ => return,
+        };
+        if let Some(digits) = self.item(path).and_then(Item::as_integer) {
+            if !(min..=MAX_DECIMALS as i64).contains(&digits) {
+                let message = format!("{} {} is out of range ({}-{})", key, digits, min, MAX_DECIMALS);
+                self.report(path, Target::Value, message);
+            }
+        }
+    }
+
+    fn check_color(&mut self, path: &[Step], allow_empty: bool) {
+        if let Some(color) = self.str_at(path) {
+            if !(allow_empty && color.is_empty()) && parse_hex_color(&color).is_none() {
+                self.report(path, Target::Value, format!("'{}' is not a #rrggbb colour", color));
+            }
+        }
+    }
+
+    /// Symbols of the configured coins, or the defaults if none are listed.
+    fn coin_symbols(&self) -> Vec<String> {
+        match self.item(&[Step::key("coins")]).and_then(Item::as_array_of_tables) {
+            Some(coins) => coins.iter()
+                .map(|c| c.get("symbol").and_then(Item::as_str).unwrap_or("").to_string())
+                .collect(),
+            None => Config::default().coins.into_iter().map(|c| c.symbol).collect(),
+        }
+    }
+
+    /// Symbols of the `[[rates]]` pairs.
+    fn rate_symbols(&self) -> Vec<String> {
+        self.item(&[Step::key("rates")])
+            .and_then(Item::as_array_of_tables)
+            .map(|rates| rates.iter()
+                .map(|r| r.get("symbol").and_then(Item::as_str).unwrap_or("").to_string())
+                .collect())
+            .unwrap_or_default()
+    }
+
+    fn item(&self, path: &[Step]) -> Option<&Item> {
+        path.iter().try_fold(self.doc.as_item(), |item, step| match step {
+            Step::Key(key) => item.get(key.as_str()),
+            Step::Index(i) => item.get(*i),
+        })
+    }
+
+    fn str_at(&self, path: &[Step]) -> Option<String> {
+        self.item(path).and_then(Item::as_str).map(str::to_string)
+    }
+
+    /// Byte range of the key or value at `path`, falling back to the
+    /// nearest enclosing item that has one.
+    fn span(&self, path: &[Step], target: Target) -> Option<Range<usize>> {
+        let (last, parent) = path.split_last()?;
+
+        let own = match (last, target) {
+            (Step::Key(key), Target::Key) => self.item(parent)
+                .and_then(Item::as_table_like)
+                .and_then(|t| t.get_key_value(key))
+                .and_then(|(k, _)| k.span()),
+            _ => self.item(path).and_then(Item::span),
+        };
+
+        own.or_else(|| self.span(parent, Target::Key))
+    }
+
+    fn report(&mut self, path: &[Step], target: Target, message: String) {
+        let offset = self.span(path, target).map(|s| s.start).unwrap_or(0);
+        let (line, column) = line_column(self.contents, offset);
+        self.problems.push(Problem { line, column, message });
+    }
+}
+
+/// Convert a serde_ignored path like `coins.0.colour` into steps.
+fn path_steps(path: &serde_ignored::Path) -> Vec<Step> {
+    use serde_ignored::Path;
+
+    match path {
+        Path::Root => Vec::new(),
+        Path::Seq { parent, index } => {
+            let mut steps = path_steps(parent);
+            steps.push(Step::Index(*index));
+            steps
+        }
+        Path::Map { parent, key } => {
+            let mut steps = path_steps(parent);
+            steps.push(Step::Key(key.clone()));
+            steps
+        }
+        Path::Some { parent } | Path::NewtypeStruct { parent } | Path::NewtypeVariant { parent } => {
+            path_steps(parent)
+        }
+    }
+}
+
+/// 1-based line and column (in characters) of a byte offset.
+fn line_column(contents: &str, offset: usize) -> (usize, usize) {
+    let before = &contents[..offset.min(contents.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+    (line, column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A coin whose icon exists, so it adds no problems of its own.
+    fn coin(symbol: &str) -> String {
+        format!(
+            "[[coins]]\nsymbol = \"{}\"\nname = \"Coin\"\nicon = \"{}/icons/btc.svg\"\n",
+            symbol,
+            env!("CARGO_MANIFEST_DIR"),
+        )
+    }
+
+    fn problems(contents: &str) -> Vec<String> {
+        check(contents).iter().map(Problem::to_string).collect()
+    }
+
+    #[test]
+    fn clean_config_has_no_problems() {
+        let contents = format!("[animation]\nfps = 60\n\n{}", coin("BTC/USD"));
+        assert!(problems(&contents).is_empty());
+    }
+
+    #[test]
+    fn reports_unknown_keys() {
+        let contents = format!("[position]\nwidht = 300\n\n{}colour = \"#ff0000\"\n", coin("BTC/USD"));
+        assert_eq!(problems(&contents), ["2:1: unknown key 'widht'", "8:1: unknown key 'colour'"]);
+    }
+
+    #[test]
+    fn reports_bad_colours() {
+        let contents = "[appearance]\ncolor_up = \"red\"\ncolor_down = \"#ff00gg\"\n";
+        assert_eq!(problems(contents), [
+            "2:12: 'red' is not a #rrggbb colour",
+            "3:14: '#ff00gg' is not a #rrggbb colour",
+        ]);
+    }
+
+    #[test]
+    fn reports_missing_icons() {
+        let contents = "[[coins]]\nsymbol = \"BTC/USD\"\nname = \"Bitcoin\"\nicon = \"no-such-icon.svg\"\n";
+        assert_eq!(problems(contents), ["4:8: icon file 'no-such-icon.svg' not found"]);
+    }
+
+    #[test]
+    fn reports_duplicate_symbols() {
+        let contents = format!("{}\n{}", coin("BTC/USD"), coin("BTC/USD"));
+        assert_eq!(problems(&contents), ["7:10: BTC/USD is listed more than once"]);
+    }
+
+    #[test]
+    fn reports_out_of_range_fps() {
+        assert_eq!(problems("[animation]\nfps = 0\n"), ["2:7: fps 0 is out of range (1-120)"]);
+        assert_eq!(problems("[animation]\nfps = 500\n"), ["2:7: fps 500 is out of range (1-120)"]);
+    }
+
+    #[test]
+    fn reports_unsupported_proxies() {
+        assert!(problems("[network]\nproxy = \"socks5h://127.0.0.1:1080\"\n").is_empty());
+        assert_eq!(
+            problems("[network]\nproxy = \"https://proxy.corp:3128\"\n"),
+            ["2:9: https:// proxies aren't supported (use http://, socks5:// or socks5h://)"],
+        );
+        assert_eq!(
+            problems("[network]\nproxy = \"proxy.corp:3128\"\n"),
+            ["2:9: 'proxy.corp:3128' is not an http://, socks5:// or socks5h:// proxy URL"],
+        );
+    }
+
+    #[test]
+    fn reports_syntax_errors_where_they_are() {
+        let problems = check("[position]\nanchor = top\n");
+        assert_eq!((problems.len(), problems[0].line, problems[0].column), (1, 2, 10));
+    }
+
+    #[test]
+    fn columns_count_characters() {
+        assert_eq!(line_column("a = \"€\"\nb", 0), (1, 1));
+        assert_eq!(line_column("a = \"€\"\nb", "a = \"€\"".len()), (1, 8));
+        assert_eq!(line_column("a = \"€\"\nb", "a = \"€\"\n".len()), (2, 1));
+    }
+}
@@ -0,0 +1,224 @@
+//! Waybar custom-module output (`--waybar`).
+//!
+//! Runs the price feeds without GTK and prints one JSON object per line for
+//! a Waybar `custom` module with `"return-type": "json"`. One coin is shown
+//! at a time, rotating on a timer; the tooltip lists every coin.
+//!
+//! Waybar sends `SIGRTMIN+N` to its modules' scripts; any of `SIGRTMIN+1`
+//! to `SIGRTMIN+10` skips to the next coin, so a module can cycle on click
+//! with `pkill -RTMIN+N -f 'waybar-crypto-ticker --waybar'`.
+
+use crate::config::Config;
+use crate::ticker::{escape_markup, ConnectionState, Segment, TickerState};
+use crate::{notify, websocket};
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How often the output is re-evaluated.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 24h change that maps to `percentage` 0 or 100 (50 is unchanged).
+const PERCENTAGE_RANGE: f64 = 10.0;
+
+/// Realtime signals received and not yet handled.
+static SKIP_REQUESTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Options for `--waybar`.
+pub struct WaybarOptions {
+    /// Time each coin is shown; `None` only cycles on signals.
+    pub rotate: Option<Duration>,
+}
+
+/// Print Waybar JSON until stdout closes.
+pub fn run(options: WaybarOptions) -> std::io::Result<()> {
+    let config = Config::load();
+    let state = Arc::new(Mutex::new(TickerState::new(&config)));
+
+    handle_realtime_signals();
+
+    // Keep the sender alive: dropping it would stop the feeds
+    let (_config_tx, config_rx) = tokio::sync::watch::channel(config.clone());
+    let state_ws = Arc::clone(&state);
+    std::thread::spawn(move || {
+        websocket::run(&state_ws, config_rx);
+    });
+
+    let mut stdout = std::io::stdout().lock();
+    let mut index = 0;
+    let mut shown_since = Instant::now();
+    let mut last_refresh = Instant::now();
+    let mut last_output = String::new();
+    let mut notifier: Option<notify::Notifier> = None;
+
+    loop {
+        let skips = SKIP_REQUESTS.swap(0, Ordering::Relaxed);
+        if skips > 0 {
+            index += skips;
+            shown_since = Instant::now();
+        } else if options.rotate.is_some_and(|r| shown_since.elapsed() >= r) {
+            index += 1;
+            shown_since = Instant::now();
+        }
+
+        let (output, events) = match state.lock() {
+            Ok(mut state) => {
+                // Stale ages tick over once a second
+                if last_refresh.elapsed() >= Duration::from_secs(1) {
+                    state.refresh();
+                    last_refresh = Instant::now();
+                }
+
+                let coins: Vec<&Segment> = state.segments.iter()
+                    .filter(|s| s.symbol.is_some())
+                    .collect();
+                if !coins.is_empty() {
+                    index %= coins.len();
+                }
+                let output = render(&coins, index, state.connection_state());
+                (output, state.take_alert_events())
+            }
+            // Skip this frame
+            Err(_) => {
+                std::thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+        };
+
+        if !events.is_empty() {
+            let notifier = notifier.get_or_insert_with(notify::Notifier::new);
+            for event in &events {
+                notifier.send_blocking(event);
+            }
+        }
+
+        if output != last_output {
+            // Waybar closes the pipe when it reloads; exit quietly
+            writeln!(stdout, "{}", output)?;
+            stdout.flush()?;
+            last_output = output;
+        }
+
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// One line of module output for the coin at `index`.
+fn render(coins: &[&Segment], index: usize, connection: ConnectionState) -> String {
+    let segment = match coins.get(index) {
+        Some(segment) => segment,
+        None => {
+            let text = match connection {
+                ConnectionState::BackingOff => "Reconnecting...",
+                _ => "Connecting...",
+            };
+            return serde_json::json!({
+                "text": text,
+                "tooltip": "Waiting for prices",
+                "class": "connecting",
+            }).to_string();
+        }
+    };
+
+    // Waybar parses both fields as Pango markup
+    let tooltip: Vec<String> = coins.iter().map(|s| escape_markup(&s.text)).collect();
+
+    let mut class = vec![segment.direction.class()];
+    if connection != ConnectionState::Live {
+        class.push("degraded");
+    }
+
+    let percentage = segment.change_pct
+        .map(|c| (50.0 + c / PERCENTAGE_RANGE * 50.0).clamp(0.0, 100.0).round() as u8)
+        .unwrap_or(50);
+
+    serde_json::json!({
+        "text": segment.markup,
+        "tooltip": tooltip.join("\n"),
+        "class": class,
+        "percentage": percentage,
+    }).to_string()
+}
+
+extern "C" fn on_realtime_signal(_: libc::c_int) {
+    SKIP_REQUESTS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Cycle coins on Waybar's realtime signals instead of ignoring them.
+fn handle_realtime_signals() {
+    unsafe {
+        let rtmin = libc::SIGRTMIN();
+        for i in 1..=10 {
+            libc::signal(rtmin + i, on_realtime_signal as *const () as libc::sighandler_t);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn state() -> TickerState {
+        let config = Config::parse(r#"
+            [numbers]
+            locale = "en_US"
+
+            [[coins]]
+            symbol = "BTC/USD"
+            name = "Bits & <Bobs>"
+            icon = "btc.svg"
+            format = "{name} {price}"
+
+            [[coins]]
+            symbol = "ETH/USD"
+            name = "Ether"
+            icon = "eth.svg"
+            format = "{name} {price}"
+        "#).unwrap();
+        let mut state = TickerState::new(&config);
+        state.update_price("BTC/USD", 67000.0, Some(66000.0), &Default::default());
+        state.update_price("ETH/USD", 3000.0, Some(3100.0), &Default::default());
+        state
+    }
+
+    fn output(state: &TickerState, index: usize, connection: ConnectionState) -> Value {
+        let coins: Vec<&Segment> = state.segments.iter().filter(|s| s.symbol.is_some()).collect();
+        serde_json::from_str(&render(&coins, index, connection)).unwrap()
+    }
+
+    #[test]
+    fn renders_the_coin_at_index() {
+        let state = state();
+
+        let btc = output(&state, 0, ConnectionState::Live);
+        assert_eq!(btc["class"], json!(["up"]));
+        assert_eq!(btc["percentage"], 58);
+        assert_eq!(btc["tooltip"], "Bits &amp; &lt;Bobs&gt; $67,000\nEther $3,000");
+
+        let eth = output(&state, 1, ConnectionState::Live);
+        assert_eq!(eth["class"], json!(["down"]));
+        assert_eq!(eth["percentage"], 34);
+    }
+
+    #[test]
+    fn escapes_text_as_pango_markup() {
+        let text = output(&state(), 0, ConnectionState::Live)["text"].as_str().unwrap().to_string();
+        assert!(text.contains("Bits &amp; &lt;Bobs&gt;"), "{}", text);
+        assert!(!text.contains("<Bobs>"), "{}", text);
+    }
+
+    #[test]
+    fn marks_unhealthy_feeds_degraded() {
+        let output = output(&state(), 0, ConnectionState::Stale);
+        assert_eq!(output["class"], json!(["up", "degraded"]));
+    }
+
+    #[test]
+    fn waits_for_prices() {
+        let output: Value = serde_json::from_str(&render(&[], 0, ConnectionState::BackingOff)).unwrap();
+        assert_eq!(output["text"], "Reconnecting...");
+        assert_eq!(output["class"], "connecting");
+    }
+}
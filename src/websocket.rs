@@ -1,148 +1,334 @@
-//! WebSocket connection to Kraken for real-time price updates.
+//! WebSocket connection management for real-time price updates.
+//!
+//! Each exchange implements [`PriceSource`]; this module drives one
+//! connection task per source and feeds parsed ticks into [`TickerState`].
 
-use crate::config::Config;
-use crate::ticker::TickerState;
+use crate::config::{Config, Network, Source};
+use crate::ticker::{ConnectionState, MarketStats, TickerState};
+use crate::{binance, bitstamp, coinbase, kraken, network};
 use futures_util::{SinkExt, StreamExt};
-use serde::{Deserialize, Serialize};
+use rand::Rng;
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
-use tokio_tungstenite::{connect_async, tungstenite::Message};
-
-const KRAKEN_WS: &str = "wss://ws.kraken.com/v2";
-const KRAKEN_REST: &str = "https://api.kraken.com/0/public/Ticker";
-
-/// Kraken REST API uses different symbol names than WebSocket.
-fn ws_to_rest_symbol(ws_symbol: &str) -> String {
-    match ws_symbol {
-        "BTC/USD" => "XXBTZUSD".to_string(),
-        "ETH/USD" => "XETHZUSD".to_string(),
-        "XRP/USD" => "XXRPZUSD".to_string(),
-        other => other.replace("/", "").to_uppercase(),
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use tokio_tungstenite::tungstenite::Message;
+
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A single price update parsed from an exchange message.
+pub struct Tick {
+    /// Symbol as written in the config (e.g. `BTC/USD`).
+    pub symbol: String,
+    pub price: f64,
+    /// 24h open, for exchanges that include it in their ticker payload.
+    pub open_24h: Option<f64>,
+    pub stats: MarketStats,
+}
+
+/// An exchange that streams prices over a WebSocket.
+///
+/// Implementations are constructed with the config symbols they serve and are
+/// responsible for translating to and from the exchange's own pair names.
+pub trait PriceSource: Send + Sync {
+    /// Human-readable exchange name used in log messages.
+    fn name(&self) -> &'static str;
+
+    /// WebSocket endpoint to connect to.
+    fn ws_url(&self) -> String;
+
+    /// Text frames to send after connecting.
+    fn subscribe_messages(&self) -> Vec<String>;
+
+    /// Parse a text frame into zero or more ticks.
+    fn parse_message(&self, text: &str) -> Vec<Tick>;
+
+    /// Whether 24h opens come from `fetch_open_prices` rather than the
+    /// ticker payload. Only these sources get an open-refresh task.
+    fn fetches_opens(&self) -> bool {
+        false
+    }
+
+    /// Fetch 24h open prices over REST, keyed by config symbol.
+    ///
+    /// This is blocking and is run off the async runtime. Sources whose
+    /// ticker payload already carries the open can keep the default.
+    fn fetch_open_prices(&self) -> Result<HashMap<String, f64>, BoxError> {
+        Ok(HashMap::new())
+    }
+
+    /// Fetch roughly `window` of recent prices per config symbol, oldest
+    /// first, to backfill sparklines at startup. Blocking, like
+    /// `fetch_open_prices`. Sources without a history endpoint keep the default.
+    fn fetch_history(&self, _window: Duration) -> Result<HashMap<String, Vec<(SystemTime, f64)>>, BoxError> {
+        Ok(HashMap::new())
     }
+
+    /// Ask the exchange which config symbols it doesn't list, for
+    /// `check-config --online`. Blocking.
+    fn unknown_symbols(&self) -> Result<Vec<String>, BoxError>;
 }
 
-#[derive(Serialize)]
-struct SubscribeMessage {
-    method: String,
-    params: SubscribeParams,
+/// Build one price source per exchange referenced in the config (coins and
+/// exchange-rate pairs), preserving the order in which exchanges first
+/// appear.
+pub fn sources_for(config: &Config) -> Vec<Arc<dyn PriceSource>> {
+    let mut grouped: Vec<(Source, Vec<String>)> = Vec::new();
+    for (source, symbol) in config.feeds() {
+        match grouped.iter_mut().find(|(s, _)| *s == source) {
+            Some((_, symbols)) => symbols.push(symbol),
+            None => grouped.push((source, vec![symbol])),
+        }
+    }
+
+    grouped.into_iter()
+        .map(|(source, symbols)| -> Arc<dyn PriceSource> {
+            match source {
+                Source::Kraken => Arc::new(kraken::Kraken::new(symbols, &config.network)),
+                Source::Coinbase => Arc::new(coinbase::Coinbase::new(symbols, &config.network)),
+                Source::Binance => Arc::new(binance::Binance::new(symbols, &config.network)),
+                Source::Bitstamp => Arc::new(bitstamp::Bitstamp::new(symbols, &config.network)),
+            }
+        })
+        .collect()
 }
 
-#[derive(Serialize)]
-struct SubscribeParams {
-    channel: String,
-    symbol: Vec<String>,
-    snapshot: bool,
+/// Main WebSocket loop: one reconnecting connection task per price source.
+///
+/// Runs until the config sender is dropped. Whenever a new config is sent
+/// (e.g. a coin added over the control socket), every source is restarted
+/// with it.
+#[tokio::main]
+pub async fn run(state: &Arc<Mutex<TickerState>>, mut config: watch::Receiver<Config>) {
+    loop {
+        let current = config.borrow_and_update().clone();
+
+        // Sources that were dropped must not keep reporting their old state
+        if let Ok(mut state) = state.lock() {
+            state.clear_connection_states();
+        }
+        let mut tasks = spawn_sources(state, &current);
+
+        // Keep the sources running until the config changes
+        if config.changed().await.is_err() {
+            while tasks.join_next().await.is_some() {}
+            return;
+        }
+        tasks.abort_all();
+    }
 }
 
-#[derive(Deserialize)]
-struct WsMessage {
-    channel: Option<String>,
-    data: Option<Vec<TickerData>>,
+/// Spawn the connection, open-refresh and history tasks for every source.
+fn spawn_sources(state: &Arc<Mutex<TickerState>>, config: &Config) -> JoinSet<()> {
+    let mut tasks = JoinSet::new();
+
+    // Backfill enough history for the longest sparkline
+    let history_window = config.coins.iter()
+        .filter_map(|c| c.sparkline.as_ref().map(|s| s.window))
+        .max();
+
+    for source in sources_for(config) {
+        if let Some(window) = history_window {
+            tasks.spawn(backfill_history(Arc::clone(state), Arc::clone(&source), window));
+        }
+        if source.fetches_opens() {
+            tasks.spawn(refresh_opens(Arc::clone(state), Arc::clone(&source), config.network.clone()));
+        }
+        tasks.spawn(run_source(Arc::clone(state), source, config.network.clone()));
+    }
+
+    tasks
 }
 
-#[derive(Deserialize)]
-struct TickerData {
-    symbol: Option<String>,
-    last: Option<f64>,
+/// Exponential reconnect delay with jitter.
+struct Backoff {
+    initial: Duration,
+    max: Duration,
+    attempt: u32,
 }
 
-#[derive(Deserialize)]
-struct RestResponse {
-    result: Option<HashMap<String, RestTicker>>,
+impl Backoff {
+    fn new(network: &Network) -> Self {
+        Self {
+            initial: network.backoff_initial,
+            max: network.backoff_max,
+            attempt: 0,
+        }
+    }
+
+    /// Delay before the next attempt: a random point in the upper half of
+    /// `initial * 2^attempt`, capped at `max`, so clients don't retry in lockstep.
+    fn next_delay(&mut self) -> Duration {
+        let ceiling = self.initial
+            .saturating_mul(2u32.saturating_pow(self.attempt))
+            .min(self.max);
+        self.attempt = self.attempt.saturating_add(1);
+        ceiling.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+
+    fn reset(&mut self) {
+        self.attempt = 0;
+    }
 }
 
-#[derive(Deserialize)]
-struct RestTicker {
-    o: Option<String>,
+fn set_connection_state(state: &Arc<Mutex<TickerState>>, source: &dyn PriceSource, cs: ConnectionState) {
+    if let Ok(mut state) = state.lock() {
+        state.set_connection_state(source.name(), cs);
+    }
 }
 
-/// Fetch 24h open prices from REST API for percentage calculation.
-fn fetch_open_prices(state: &Arc<Mutex<TickerState>>, config: &Config) {
-    let pairs: Vec<String> = config.coins.iter()
-        .map(|c| ws_to_rest_symbol(&c.symbol))
-        .collect();
+/// Seed sparkline history from the source's REST API, once at startup.
+async fn backfill_history(
+    state: Arc<Mutex<TickerState>>,
+    source: Arc<dyn PriceSource>,
+    window: Duration,
+) {
+    let fetcher = Arc::clone(&source);
+    match tokio::task::spawn_blocking(move || fetcher.fetch_history(window)).await {
+        Ok(Ok(history)) => {
+            if let Ok(mut state) = state.lock() {
+                for (symbol, points) in history {
+                    state.backfill_history(&symbol, &points);
+                }
+            }
+        }
+        Ok(Err(e)) => eprintln!("{} history fetch failed: {}", source.name(), e),
+        Err(e) => eprintln!("{} history fetch panicked: {}", source.name(), e),
+    }
+}
 
-    let url = format!("{}?pair={}", KRAKEN_REST, pairs.join(","));
+/// Keep a source's 24h opens current: fetch at startup, then every
+/// `open_refresh`, retrying failures with backoff.
+async fn refresh_opens(
+    state: Arc<Mutex<TickerState>>,
+    source: Arc<dyn PriceSource>,
+    network: Network,
+) {
+    let mut backoff = Backoff::new(&network);
+
+    loop {
+        let fetcher = Arc::clone(&source);
+        let result = tokio::task::spawn_blocking(move || fetcher.fetch_open_prices()).await;
 
-    if let Ok(resp) = reqwest::blocking::get(&url) {
-        if let Ok(data) = resp.json::<RestResponse>() {
-            if let Some(result) = data.result {
+        let delay = match result {
+            Ok(Ok(opens)) => {
                 if let Ok(mut state) = state.lock() {
-                    for coin in &config.coins {
-                        let rest_sym = ws_to_rest_symbol(&coin.symbol);
-                        if let Some(ticker) = result.get(&rest_sym) {
-                            if let Some(open_str) = &ticker.o {
-                                if let Ok(open) = open_str.parse::<f64>() {
-                                    state.set_open_price(&coin.symbol, open);
-                                }
-                            }
-                        }
+                    for (symbol, open) in opens {
+                        state.set_open_price(&symbol, open);
                     }
                 }
+                backoff.reset();
+                match network.open_refresh {
+                    Some(interval) => interval,
+                    None => return,
+                }
             }
-        }
+            Ok(Err(e)) => {
+                eprintln!("{} open price fetch failed: {}", source.name(), e);
+                backoff.next_delay()
+            }
+            Err(e) => {
+                eprintln!("{} open price fetch panicked: {}", source.name(), e);
+                backoff.next_delay()
+            }
+        };
+
+        tokio::time::sleep(delay).await;
     }
 }
 
-/// Main WebSocket loop with automatic reconnection.
-#[tokio::main]
-pub async fn run(state: &Arc<Mutex<TickerState>>, config: &Config) {
-    fetch_open_prices(state, config);
-
-    let symbols: Vec<String> = config.coins.iter()
-        .map(|c| c.symbol.clone())
-        .collect();
+/// Stream from a source with automatic reconnection. REST opens are kept
+/// current by the separate `refresh_opens` task.
+async fn run_source(
+    state: Arc<Mutex<TickerState>>,
+    source: Arc<dyn PriceSource>,
+    network: Network,
+) {
+    let mut backoff = Backoff::new(&network);
 
     loop {
-        if let Err(e) = connect_and_stream(state, &symbols).await {
-            eprintln!("WebSocket error: {:?}", e);
+        set_connection_state(&state, source.as_ref(), ConnectionState::Connecting);
+
+        match connect_and_stream(&state, source.as_ref(), &network, &mut backoff).await {
+            Ok(()) => eprintln!("{} WebSocket closed", source.name()),
+            Err(e) => eprintln!("{} WebSocket error: {:?}", source.name(), e),
         }
-        tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+
+        set_connection_state(&state, source.as_ref(), ConnectionState::BackingOff);
+        tokio::time::sleep(backoff.next_delay()).await;
     }
 }
 
 async fn connect_and_stream(
     state: &Arc<Mutex<TickerState>>,
-    symbols: &[String],
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let (ws_stream, _) = connect_async(KRAKEN_WS).await?;
+    source: &dyn PriceSource,
+    network: &Network,
+    backoff: &mut Backoff,
+) -> Result<(), BoxError> {
+    let ws_stream = network::connect_ws(&source.ws_url(), network).await?;
     let (mut write, mut read) = ws_stream.split();
 
-    let subscribe = SubscribeMessage {
-        method: "subscribe".to_string(),
-        params: SubscribeParams {
-            channel: "ticker".to_string(),
-            symbol: symbols.to_vec(),
-            snapshot: true,
-        },
-    };
-
-    write.send(Message::Text(serde_json::to_string(&subscribe)?)).await?;
-
-    while let Some(msg) = read.next().await {
-        match msg {
-            Ok(Message::Text(text)) => {
-                if let Ok(ws_msg) = serde_json::from_str::<WsMessage>(&text) {
-                    if ws_msg.channel.as_deref() == Some("ticker") {
-                        if let Some(data) = ws_msg.data {
-                            if let Ok(mut state) = state.lock() {
-                                for ticker in data {
-                                    if let (Some(symbol), Some(price)) = (ticker.symbol, ticker.last) {
-                                        state.update_price(&symbol, price);
-                                    }
-                                }
+    for subscribe in source.subscribe_messages() {
+        write.send(Message::Text(subscribe)).await?;
+    }
+
+    // Idle watchdog. Any frame (ticks, heartbeats, pings) shows the
+    // connection is alive; only nothing at all for the full timeout drops
+    // it. Ticker data is tracked separately: without ticks for half the
+    // timeout the feed is flagged stale, though a quiet pair's heartbeats
+    // keep it connected, and its coins dim once older than `stale_after`.
+    let mut last_frame = Instant::now();
+    let mut last_tick = Instant::now();
+    let mut current = ConnectionState::Connecting;
+    let mut watchdog = tokio::time::interval(Duration::from_secs(1));
+
+    loop {
+        tokio::select! {
+            msg = read.next() => {
+                last_frame = Instant::now();
+
+                match msg {
+                    Some(Ok(Message::Text(text))) => {
+                        let ticks = source.parse_message(&text);
+                        if ticks.is_empty() {
+                            continue;
+                        }
+
+                        last_tick = Instant::now();
+                        if let Ok(mut state) = state.lock() {
+                            for tick in ticks {
+                                state.update_price(&tick.symbol, tick.price, tick.open_24h, &tick.stats);
+                            }
+                            if current != ConnectionState::Live {
+                                state.set_connection_state(source.name(), ConnectionState::Live);
                             }
                         }
+                        if current != ConnectionState::Live {
+                            current = ConnectionState::Live;
+                            backoff.reset();
+                        }
+                    }
+                    Some(Ok(Message::Ping(data))) => {
+                        let _ = write.send(Message::Pong(data)).await;
                     }
+                    Some(Ok(Message::Close(_))) | None => return Ok(()),
+                    Some(Err(e)) => return Err(Box::new(e)),
+                    Some(Ok(_)) => {}
                 }
             }
-            Ok(Message::Ping(data)) => {
-                let _ = write.send(Message::Pong(data)).await;
+            _ = watchdog.tick(), if network.idle_timeout.is_some() => {
+                let idle_timeout = network.idle_timeout.unwrap_or_default();
+                let silent = last_frame.elapsed();
+
+                if silent >= idle_timeout {
+                    return Err(format!("nothing received for {}s", silent.as_secs()).into());
+                }
+                if last_tick.elapsed() >= idle_timeout / 2 && current != ConnectionState::Stale {
+                    current = ConnectionState::Stale;
+                    set_connection_state(state, source, current);
+                }
             }
-            Err(e) => return Err(Box::new(e)),
-            _ => {}
         }
     }
-
-    Ok(())
 }